@@ -0,0 +1,164 @@
+// Lowers the bundled JSONLang libraries to IR so the compiler doesn't parse them on every run.
+// The compiler passes are shared with the crate, most of what they define isn't used here.
+#![allow(dead_code)]
+
+#[path = "src/jsonlang.rs"]
+mod jsonlang;
+#[path = "src/control_simplification.rs"]
+mod control_simplification;
+#[path = "src/variable_analysis.rs"]
+mod variable_analysis;
+#[path = "src/fixup_types.rs"]
+mod fixup_types;
+#[path = "src/polyfill_ops.rs"]
+mod polyfill_ops;
+#[path = "src/polyfill_registry.rs"]
+mod polyfill_registry;
+#[path = "src/strength_reduction.rs"]
+mod strength_reduction;
+#[path = "src/options.rs"]
+mod options;
+#[path = "src/cordic"]
+mod cordic {
+  pub mod cordic_polyfill;
+}
+#[path = "src/math"]
+mod math {
+  pub mod math_polyfill;
+}
+#[path = "src/stdlib"]
+mod stdlib {
+  pub mod stdlib_polyfill;
+}
+
+// Stands in for the module generated here, the libraries are lowered without linking them
+mod libraries {
+  use crate::polyfill_registry::Library;
+  use crate::variable_analysis::Function;
+  use crate::polyfill_ops::Statement;
+
+  pub fn library_function(library: &Library, name: &str) -> Function<Statement> {
+    unreachable!("Polyfill {} of the {} library linked while generating the libraries", name, library.name)
+  }
+  pub fn library_table(library: &Library, name: &str) -> Vec<f64> {
+    unreachable!("Table {} of the {} library linked while generating the libraries", name, library.name)
+  }
+}
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use control_simplification::top_simplify_control_structures;
+use variable_analysis::{ top_analyze_variables, Function };
+use fixup_types::fix_types;
+use polyfill_ops::{ unlinked_polyfill_ops, Statement, Expression };
+use polyfill_registry::{ Library, Polyfill, PolyfillBody, Reference, relocate_calls };
+use options::CompilerOptions;
+
+fn library_polyfills() -> Vec<(&'static Library, &'static [Polyfill])> {
+  vec![
+    (&cordic::cordic_polyfill::CORDIC_LIBRARY, cordic::cordic_polyfill::CORDIC_POLYFILLS),
+    (&math::math_polyfill::MATH_LIBRARY, math::math_polyfill::MATH_POLYFILLS),
+    (&stdlib::stdlib_polyfill::STDLIB_LIBRARY, stdlib::stdlib_polyfill::STDLIB_POLYFILLS)
+  ]
+}
+
+struct LoweredLibrary {
+  functions: Vec<(&'static str, Function<Statement>)>,
+  tables: Vec<(String, Vec<f64>)>
+}
+
+fn dependency(polyfill: &Polyfill, name: &str) -> u32 {
+  polyfill.dependencies.iter().position(|dependency| *dependency == name)
+    .unwrap_or_else(|| panic!("Polyfill {} uses {} which isn't one of its dependencies", polyfill.name, name)) as u32
+}
+
+// Lowers a library and returns the body of each of its polyfills, with calls relocated like PolyfillBody::Ir,
+// and its tables with polyfill names
+fn lower_library(library: &Library, polyfills: &[Polyfill]) -> LoweredLibrary {
+  let p = serde_json::from_str(library.source)
+    .unwrap_or_else(|err| panic!("Couldn't parse the {} library: {}", library.name, err));
+  let program = unlinked_polyfill_ops(fix_types(top_analyze_variables(top_simplify_control_structures(p))), &CompilerOptions::default());
+  // Polyfill name of every function in the library, by library index
+  let names: HashMap<u32, String> = program.funcname_map.iter().map(|(name, index)| {
+    (*index, if name.starts_with('#') { name.clone() } else { format!("#{}", name) })
+  }).collect();
+  let tables: Vec<(String, Vec<f64>)> = program.tables.iter()
+    .map(|table| (format!("#{}", table.name), table.values.clone())).collect();
+  let functions = polyfills.iter().filter(|polyfill| matches!(polyfill.body, PolyfillBody::JsonLang(l) if l.name == library.name)).map(|polyfill| {
+    let index = *program.funcname_map.get(&polyfill.name[1..])
+      .unwrap_or_else(|| panic!("Polyfill {} not found in {} library", polyfill.name, library.name));
+    let func = program.functions.get(&index).unwrap().clone();
+    let func = relocate_calls(polyfill, func, &mut |reference| match reference {
+      Reference::Call(index) => dependency(polyfill, names.get(&index).unwrap()),
+      Reference::Table(index) => dependency(polyfill, &tables[index as usize].0)
+    });
+    (polyfill.name, func)
+  }).collect();
+  LoweredLibrary { functions, tables }
+}
+
+fn number(n: f64) -> String {
+  format!("f64::from_bits({:#x})", n.to_bits())
+}
+
+fn list<T>(items: &[T], f: fn(&T) -> String) -> String {
+  format!("vec![{}]", items.iter().map(f).collect::<Vec<String>>().join(", "))
+}
+
+fn statement(stmt: &Statement) -> String {
+  match stmt {
+    Statement::Loop { body, step } => format!("Statement::Loop {{ body: {}, step: {} }}", list(body, statement), list(step, statement)),
+    Statement::If { cond, then, otherwise } => format!("Statement::If {{ cond: {}, then: {}, otherwise: {} }}",
+      expression(cond), list(then, statement), list(otherwise, statement)),
+    Statement::Switch { index, targets, cases, default } => format!("Statement::Switch {{ index: {}, targets: vec!{:?}, cases: vec![{}], default: {} }}",
+      expression(index), targets, cases.iter().map(|case| list(case, statement)).collect::<Vec<String>>().join(", "), list(default, statement)),
+    Statement::Break(depth) => format!("Statement::Break({})", depth),
+    Statement::Continue(depth) => format!("Statement::Continue({})", depth),
+    Statement::LocalSet(index, value) => format!("Statement::LocalSet({}, {})", index, expression(value)),
+    Statement::GlobalSet(index, value) => format!("Statement::GlobalSet({}, {})", index, expression(value)),
+    Statement::Call(index, args) => format!("Statement::Call({}, {})", index, list(args, expression)),
+    Statement::Return(value) => format!("Statement::Return({})", expression(value))
+  }
+}
+
+fn expression(expr: &Expression) -> String {
+  match expr {
+    Expression::UnaryOp { op, arg } => format!("Expression::UnaryOp {{ op: UnaryOp::{:?}, arg: Box::new({}) }}", op, expression(arg)),
+    Expression::BinaryOp { lhs, op, rhs } => format!("Expression::BinaryOp {{ lhs: Box::new({}), op: BinaryOp::{:?}, rhs: Box::new({}) }}",
+      expression(lhs), op, expression(rhs)),
+    Expression::LocalGet(index) => format!("Expression::LocalGet({})", index),
+    Expression::LocalTee(index, value) => format!("Expression::LocalTee({}, Box::new({}))", index, expression(value)),
+    Expression::GlobalGet(index) => format!("Expression::GlobalGet({})", index),
+    Expression::FunctionCall(index, args) => format!("Expression::FunctionCall({}, {})", index, list(args, expression)),
+    Expression::TableGet(table, index) => format!("Expression::TableGet({}, Box::new({}))", table, expression(index)),
+    Expression::Conditional { cond, then, otherwise } => format!("Expression::Conditional {{ cond: Box::new({}), then: Box::new({}), otherwise: Box::new({}) }}",
+      expression(cond), expression(then), expression(otherwise)),
+    Expression::NumericLiteral(n) => format!("Expression::NumericLiteral({})", number(*n))
+  }
+}
+
+fn main() {
+  println!("cargo:rerun-if-changed=src");
+  let mut function_arms = Vec::new();
+  let mut table_arms = Vec::new();
+  for (library, polyfills) in library_polyfills() {
+    let lowered = lower_library(library, polyfills);
+    for (name, func) in lowered.functions {
+      function_arms.push(format!("    ({:?}, {:?}) => Function {{\n      arguments: {},\n      local_count: {},\n      body: {}\n    }},\n",
+        library.name, name, func.arguments, func.local_count, list(&func.body, statement)));
+    }
+    for (name, values) in lowered.tables {
+      table_arms.push(format!("    ({:?}, {:?}) => {},\n", library.name, name, list(&values, |n| number(*n))));
+    }
+  }
+  let source = format!("pub fn library_function(library: &Library, name: &str) -> Function<Statement> {{\n  \
+    match (library.name, name) {{\n{}    _ => panic!(\"Polyfill {{}} not found in {{}} library\", name, library.name)\n  }}\n}}\n\n\
+    pub fn library_table(library: &Library, name: &str) -> Vec<f64> {{\n  \
+    match (library.name, name) {{\n{}    _ => panic!(\"Table {{}} not found in {{}} library\", name, library.name)\n  }}\n}}\n",
+    function_arms.concat(), table_arms.concat());
+  let out_dir = env::var("OUT_DIR").unwrap();
+  fs::write(Path::new(&out_dir).join("libraries.rs"), source).unwrap();
+}
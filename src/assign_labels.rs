@@ -10,43 +10,102 @@ pub enum Instruction {
     then: Vec<Instruction>,
     otherwise: Vec<Instruction>
   },
+  // If with an f64 result
+  IfResult {
+    then: Vec<Instruction>,
+    otherwise: Vec<Instruction>
+  },
   Branch(u32),
+  // Branches to targets[i] for the i32 index on the stack, or to the default when it's past them
+  BranchTable {
+    targets: Vec<u32>,
+    default: u32
+  },
   Const(f64),
   LocalGet(u32),
   LocalSet(u32),
+  LocalTee(u32),
   GlobalGet(u32),
   GlobalSet(u32),
   Call(u32),
+  TableGet(u32),
   Return(),
   Drop(),
+  Select(),
   UnOp(UnaryOp),
   BinOp(BinaryOp)
 }
 
-fn add_labels(instr: UnlabaledInstruction, break_label: u32) -> Instruction {
-  let map_one_level_deeper = |body: Vec<UnlabaledInstruction>| body.into_iter()
-  .map(|inner_instr| add_labels(inner_instr, break_label + 1)).collect();
+// Depths of the labels continue and break branch to, for each loop around an instruction from the outermost
+type Loops = Vec<(u32, u32)>;
+
+// The loops seen from inside `labels` more blocks
+fn deeper(loops: &Loops, labels: u32) -> Loops {
+  loops.iter().map(|(continue_label, break_label)| (continue_label + labels, break_label + labels)).collect()
+}
+
+fn target(loops: &Loops, depth: u32) -> (u32, u32) {
+  loops[loops.len() - 1 - depth as usize]
+}
+
+fn add_labels(instr: UnlabaledInstruction, loops: &Loops) -> Instruction {
+  let map = |body: Vec<UnlabaledInstruction>, loops: &Loops| body.into_iter()
+    .map(|inner_instr| add_labels(inner_instr, loops)).collect::<Vec<Instruction>>();
   match instr {
-    UnlabaledInstruction::Loop(body) => {
-      let mut body: Vec<Instruction> = body.into_iter().map(|inner_instr| add_labels(inner_instr, 0)).collect();
-      body.push(Instruction::Branch(0));
+    // block (loop (block body) step br 0), continue in the body ends the inner block so the step still runs
+    UnlabaledInstruction::Loop { body, step } => {
+      let mut step_loops = deeper(loops, 2);
+      step_loops.push((0, 1));
+      let mut instrs = if step.is_empty() {
+        map(body, &step_loops)
+      } else {
+        let mut body_loops = deeper(loops, 3);
+        body_loops.push((0, 2));
+        vec![Instruction::Block(map(body, &body_loops))]
+      };
+      instrs.append(&mut map(step, &step_loops));
+      instrs.push(Instruction::Branch(0));
       Instruction::Block(vec![
-        Instruction::Loop(body)
+        Instruction::Loop(instrs)
       ])
     }
+    // block (block (block ... (block index br_table) case 0 br) ... case n-1 br) default), the br_table branches
+    // to the end of the block before each case and the cases branch out of the outermost one.
+    // Blocks don't take values from the stack, so the index is computed inside the innermost one
+    UnlabaledInstruction::Switch { index, targets, cases, default } => {
+      let count = cases.len() as u32;
+      let mut instrs = map(index, &deeper(loops, count + 2));
+      instrs.push(Instruction::BranchTable { targets, default: count });
+      for (index, case) in cases.into_iter().enumerate() {
+        let end = count - index as u32;
+        let mut block = vec![Instruction::Block(instrs)];
+        block.append(&mut map(case, &deeper(loops, end + 1)));
+        block.push(Instruction::Branch(end));
+        instrs = block;
+      }
+      let mut block = vec![Instruction::Block(instrs)];
+      block.append(&mut map(default, &deeper(loops, 1)));
+      Instruction::Block(block)
+    }
     UnlabaledInstruction::If { then, otherwise } => Instruction::If {
-      then: map_one_level_deeper(then), otherwise: map_one_level_deeper(otherwise)
+      then: map(then, &deeper(loops, 1)), otherwise: map(otherwise, &deeper(loops, 1))
+    },
+    UnlabaledInstruction::Conditional { then, otherwise } => Instruction::IfResult {
+      then: map(then, &deeper(loops, 1)), otherwise: map(otherwise, &deeper(loops, 1))
     },
-    UnlabaledInstruction::Break => Instruction::Branch(break_label + 1),
-    UnlabaledInstruction::Continue => Instruction::Branch(break_label),
+    UnlabaledInstruction::Break(depth) => Instruction::Branch(target(loops, depth).1),
+    UnlabaledInstruction::Continue(depth) => Instruction::Branch(target(loops, depth).0),
     UnlabaledInstruction::Const(literal) => Instruction::Const(literal),
     UnlabaledInstruction::LocalGet(index) => Instruction::LocalGet(index),
     UnlabaledInstruction::LocalSet(index) => Instruction::LocalSet(index),
+    UnlabaledInstruction::LocalTee(index) => Instruction::LocalTee(index),
     UnlabaledInstruction::GlobalGet(index) => Instruction::GlobalGet(index),
     UnlabaledInstruction::GlobalSet(index) => Instruction::GlobalSet(index),
     UnlabaledInstruction::Call(index) => Instruction::Call(index),
+    UnlabaledInstruction::TableGet(table) => Instruction::TableGet(table),
     UnlabaledInstruction::Return() => Instruction::Return(),
     UnlabaledInstruction::Drop() => Instruction::Drop(),
+    UnlabaledInstruction::Select() => Instruction::Select(),
     UnlabaledInstruction::UnOp(op) => Instruction::UnOp(op),
     UnlabaledInstruction::BinOp(op) => Instruction::BinOp(op)
   }
@@ -54,7 +113,7 @@ fn add_labels(instr: UnlabaledInstruction, break_label: u32) -> Instruction {
 
 impl From<Function<UnlabaledInstruction>> for Function<Instruction> {
   fn from(func: Function<UnlabaledInstruction>) -> Self {
-    let body = func.body.into_iter().map(|instr| add_labels(instr, 0)).collect();
+    let body = func.body.into_iter().map(|instr| add_labels(instr, &Vec::new())).collect();
     Self { arguments: func.arguments, local_count: func.local_count, body }
   }
 }
@@ -63,7 +122,10 @@ impl AnalysisResults<Instruction> {
   pub fn from_unlabaled_analysis(analysis: AnalysisResults<UnlabaledInstruction>) -> Self {
     AnalysisResults {
       global_variables: analysis.global_variables,
+      global_declarations: analysis.global_declarations,
       funcname_map: analysis.funcname_map,
+      tables: analysis.tables,
+      exports: analysis.exports,
       functions: analysis.functions.into_iter().map(|(k, v)| (k, v.into())).collect()
     }
   }
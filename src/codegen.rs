@@ -1,19 +1,34 @@
 use std::collections::HashMap;
 
-use crate::variable_analysis::{AnalysisResults, Function, UnaryOp };
+use crate::variable_analysis::{AnalysisResults, Function, Global, Table, UnaryOp };
 use crate::assign_labels::Instruction;
 use crate::polyfill_ops::{ BinaryOp };
+use crate::options::CompilerOptions;
+
+const PAGE_SIZE: usize = 65536;
+
+fn float(value: f64) -> String {
+  if value.is_nan() { "nan".to_string() } else { value.to_string() }
+}
+
+// Whether a name can be used as a $identifier in the text format
+fn is_identifier(name: &str) -> bool {
+  !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-./:<=>?@\\^_`|~".contains(c))
+}
 
 struct WASMEmitter {
   indent: u32,
-  emitted: String
+  emitted: String,
+  // Address of every table in memory
+  table_offsets: Vec<usize>
 }
 
 impl WASMEmitter {
   pub fn new() -> Self {
     WASMEmitter {
       indent: 0,
-      emitted: String::new()
+      emitted: String::new(),
+      table_offsets: Vec::new()
     }
   }
   fn emit_str(&mut self, str: &str) {
@@ -24,7 +39,7 @@ impl WASMEmitter {
     for _ in 0..self.indent { self.emitted.push(' '); }
     self.emit_str(str);
   }
-  pub fn emit_program(program: AnalysisResults<Instruction>) -> String {
+  pub fn emit_program(program: AnalysisResults<Instruction>, options: &CompilerOptions) -> String {
     let mut emitter = Self::new();
     let mut funclist = Vec::new();
     let mut functions = program.functions;
@@ -33,31 +48,76 @@ impl WASMEmitter {
     }
     emitter.emit_line("(module");
     emitter.indent += 2;
+    emitter.emit_tables(&program.tables);
     emitter.emit_types(&funclist);
-    emitter.emit_functions(&funclist);
-    emitter.emit_globals(program.global_variables.count());
-    emitter.emit_global_exports(program.global_variables.list_variables());
-    emitter.emit_func_exports(&program.funcname_map);
+    emitter.emit_functions(&funclist, &program.funcname_map);
+    let main = program.funcname_map.get("#main");
+    if let (Some(main), true) = (main, options.start) {
+      emitter.emit_start(*main, funclist.len() as u32);
+    }
+    emitter.emit_globals(program.global_variables.count(), &program.global_declarations);
+    emitter.emit_global_exports(&program.exports.globals);
+    emitter.emit_func_exports(&program.exports.functions);
+    if let (Some(main), Some(entry)) = (main, &options.entry) {
+      if program.exports.functions.values().chain(program.exports.globals.values()).any(|name| name == entry) {
+        panic!("Duplicate export {}", entry)
+      }
+      emitter.emit_line(&format!(r#"(export "{}" (func {}))"#, entry, main));
+    }
     emitter.indent -= 2;
     emitter.emit_line(")");
     emitter.emitted
   }
-  fn emit_globals(&mut self, count: u32) {
-    for _ in 0..count {
-      self.emit_line("(global (mut f64) (f64.const 0))");
+  // Tables are laid out one after the other in a memory that isn't exported, so they can't be written
+  fn emit_tables(&mut self, tables: &[Table]) {
+    if tables.is_empty() {
+      return
+    }
+    let mut offset = 0;
+    let mut data = Vec::new();
+    for table in tables {
+      self.table_offsets.push(offset);
+      let bytes: String = table.values.iter().flat_map(|value| value.to_le_bytes())
+        .map(|byte| format!("\\{:02x}", byte)).collect();
+      data.push(format!(r#"(data (i32.const {}) "{}")"#, offset, bytes));
+      offset += table.values.len() * 8;
+    }
+    self.emit_line(&format!("(memory {})", offset.div_ceil(PAGE_SIZE).max(1)));
+    for segment in data {
+      self.emit_line(&segment);
     }
   }
-  fn emit_global_exports(&mut self, globals: Vec<(&String, &u32)>) {
-    for (name, index) in globals {
+  // The start function can't return a value, so #main is called from a wrapper that drops it
+  fn emit_start(&mut self, main: u32, index: u32) {
+    self.emit_line("(type (func))");
+    self.emit_line(&format!("(func $#start (type {})", index));
+    self.indent += 2;
+    self.emit_line(&format!("call {}", main));
+    self.emit_line("drop");
+    self.indent -= 2;
+    self.emit_line(")");
+    self.emit_line(&format!("(start {})", index));
+  }
+  fn emit_globals(&mut self, count: u32, declarations: &HashMap<u32, Global>) {
+    for index in 0..count {
+      match declarations.get(&index) {
+        Some(Global { value, mutable: false }) => self.emit_line(&format!("(global f64 (f64.const {}))", float(*value))),
+        Some(Global { value, mutable: true }) => self.emit_line(&format!("(global (mut f64) (f64.const {}))", float(*value))),
+        None => self.emit_line("(global (mut f64) (f64.const 0))")
+      }
+    }
+  }
+  fn emit_global_exports(&mut self, globals: &HashMap<u32, String>) {
+    for (index, name) in globals {
       self.emit_line(&format!(r#"(export "{}" (global {}))"#, name, index));
     }
   }
-  fn emit_func_exports(&mut self, funcnames: &HashMap<String, u32>) {
-    for (name, index) in funcnames {
+  fn emit_func_exports(&mut self, funcnames: &HashMap<u32, String>) {
+    for (index, name) in funcnames {
       self.emit_line(&format!(r#"(export "{}" (func {}))"#, name, index));
     }
   }
-  fn emit_types(&mut self, funclist: &Vec<Function<Instruction>>) {
+  fn emit_types(&mut self, funclist: &[Function<Instruction>]) {
     for func in funclist.iter() {
       self.emit_line("(type (func");
       self.emit_func_type(func);
@@ -74,11 +134,10 @@ impl WASMEmitter {
     }
     self.emit_str(" (result f64)");
   }
-  fn emit_functions(&mut self, funclist: &Vec<Function<Instruction>>) {
-    let mut index = 0;
-    for func in funclist {
-      self.emit_function(func, index);
-      index += 1;
+  fn emit_functions(&mut self, funclist: &[Function<Instruction>], funcnames: &HashMap<String, u32>) {
+    let names: HashMap<u32, &String> = funcnames.iter().map(|(name, index)| (*index, name)).collect();
+    for (index, func) in funclist.iter().enumerate() {
+      self.emit_function(func, index as u32, names[&(index as u32)]);
     }
   }
   fn emit_locals(&mut self, func: &Function<Instruction>) {
@@ -90,8 +149,13 @@ impl WASMEmitter {
       self.emit_str(")");
     }
   }
-  fn emit_function(&mut self, func: &Function<Instruction>, type_index: u32) {
-    self.emit_line(&format!("(func (type {})", type_index));
+  // Functions are named after the program so unexported ones can still be told apart in the text format
+  fn emit_function(&mut self, func: &Function<Instruction>, type_index: u32, name: &str) {
+    if is_identifier(name) {
+      self.emit_line(&format!("(func ${} (type {})", name, type_index));
+    } else {
+      self.emit_line(&format!("(func (type {})", type_index));
+    }
     self.emit_func_type(func);
     self.emit_locals(func);
     self.indent += 2;
@@ -127,15 +191,34 @@ impl WASMEmitter {
         emit_body(self, otherwise);
         self.emit_line("end");
       }
+      Instruction::IfResult { then, otherwise } => {
+        self.emit_line("if (result f64)");
+        emit_body(self, then);
+        self.emit_line("else");
+        emit_body(self, otherwise);
+        self.emit_line("end");
+      }
       Instruction::Branch(index) => self.emit_line(&format!("br {}", index)),
-      Instruction::Const(literal) => self.emit_line(&format!("f64.const {}", literal)),
+      Instruction::BranchTable { targets, default } => {
+        let labels: Vec<String> = targets.iter().chain([default]).map(u32::to_string).collect();
+        self.emit_line(&format!("br_table {}", labels.join(" ")));
+      },
+      Instruction::Const(literal) => self.emit_line(&format!("f64.const {}", float(*literal))),
       Instruction::LocalGet(index) => self.emit_line(&format!("local.get {}", index)),
       Instruction::LocalSet(index) => self.emit_line(&format!("local.set {}", index)),
+      Instruction::LocalTee(index) => self.emit_line(&format!("local.tee {}", index)),
       Instruction::GlobalGet(index) => self.emit_line(&format!("global.get {}", index)),
       Instruction::GlobalSet(index) => self.emit_line(&format!("global.set {}", index)),
       Instruction::Call(index) => self.emit_line(&format!("call {}", index)),
+      Instruction::TableGet(table) => {
+        // Elements are 8 bytes apart, indices past the end aren't checked
+        self.emit_line("i32.const 3");
+        self.emit_line("i32.shl");
+        self.emit_line(&format!("f64.load offset={}", self.table_offsets[*table as usize]));
+      },
       Instruction::Return() => self.emit_line("return"),
       Instruction::Drop() => self.emit_line("drop"),
+      Instruction::Select() => self.emit_line("select"),
       Instruction::UnOp(UnaryOp::NumericNegation) => self.emit_line("f64.neg"),
       Instruction::UnOp(UnaryOp::LogicNegation) => self.emit_line("i32.eqz"),
       Instruction::UnOp(UnaryOp::BitwiseNegation) => {
@@ -146,6 +229,10 @@ impl WASMEmitter {
       Instruction::UnOp(UnaryOp::FloatToInt) => self.emit_line("i32.trunc_f64_u"),
       Instruction::UnOp(UnaryOp::Sqrt) => self.emit_line("f64.sqrt"),
       Instruction::UnOp(UnaryOp::Floor) => self.emit_line("f64.floor"),
+      Instruction::UnOp(UnaryOp::Ceil) => self.emit_line("f64.ceil"),
+      Instruction::UnOp(UnaryOp::Trunc) => self.emit_line("f64.trunc"),
+      Instruction::UnOp(UnaryOp::Nearest) => self.emit_line("f64.nearest"),
+      Instruction::UnOp(UnaryOp::Abs) => self.emit_line("f64.abs"),
       Instruction::BinOp(BinaryOp::Addition) => self.emit_line("f64.add"),
       Instruction::BinOp(BinaryOp::Substraction) => self.emit_line("f64.sub"),
       Instruction::BinOp(BinaryOp::Multiplication) => self.emit_line("f64.mul"),
@@ -160,15 +247,17 @@ impl WASMEmitter {
       Instruction::BinOp(BinaryOp::GreaterEq) => self.emit_line("f64.ge"),
       Instruction::BinOp(BinaryOp::NotEqual) => self.emit_line("f64.ne"),
       Instruction::BinOp(BinaryOp::Equal) => self.emit_line("f64.eq"),
+      Instruction::BinOp(BinaryOp::Min) => self.emit_line("f64.min"),
+      Instruction::BinOp(BinaryOp::Max) => self.emit_line("f64.max"),
+      Instruction::BinOp(BinaryOp::CopySign) => self.emit_line("f64.copysign"),
       Instruction::BinOp(BinaryOp::BitwiseAnd) => self.emit_line("i32.and"),
       Instruction::BinOp(BinaryOp::BitwiseOr) => self.emit_line("i32.or"),
       Instruction::BinOp(BinaryOp::LeftShift) => self.emit_line("i32.shl"),
       Instruction::BinOp(BinaryOp::RightShift) => self.emit_line("i32.shr_u"),
-      Instruction::BinOp(BinaryOp::Modulo) => self.emit_line("i32.rem_u"),
     }
   }
 }
 
-pub fn emit_wasm(program: AnalysisResults<Instruction>) -> String {
-  WASMEmitter::emit_program(program)
+pub fn emit_wasm(program: AnalysisResults<Instruction>, options: &CompilerOptions) -> String {
+  WASMEmitter::emit_program(program, options)
 }
\ No newline at end of file
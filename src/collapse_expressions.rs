@@ -3,25 +3,59 @@ use crate::polyfill_ops::{ Statement, Expression, BinaryOp };
 
 #[derive(Debug, Clone)]
 pub enum Instruction {
-  Loop(Vec<Instruction>),
+  // Continue jumps to the step
+  Loop {
+    body: Vec<Instruction>,
+    step: Vec<Instruction>
+  },
   If {
     then: Vec<Instruction>,
     otherwise: Vec<Instruction>
   },
-  Break,
-  Continue,
+  // If that leaves the f64 computed by the branch on the stack
+  Conditional {
+    then: Vec<Instruction>,
+    otherwise: Vec<Instruction>
+  },
+  // Jumps to cases[targets[i]] for the i32 index computed by `index`, or to the default past the targets
+  Switch {
+    index: Vec<Instruction>,
+    targets: Vec<u32>,
+    cases: Vec<Vec<Instruction>>,
+    default: Vec<Instruction>
+  },
+  // Number of loops around the innermost one that are also left
+  Break(u32),
+  Continue(u32),
   Const(f64),
   LocalGet(u32),
   LocalSet(u32),
+  LocalTee(u32),
   GlobalGet(u32),
   GlobalSet(u32),
   Call(u32),
+  // Reads the element of a table at the i32 index on the stack
+  TableGet(u32),
   Return(),
   Drop(),
+  // Picks the first of two values if the i32 on top of them isn't 0
+  Select(),
   UnOp(UnaryOp),
   BinOp(BinaryOp)
 }
 
+// Expressions that can be evaluated when their value isn't used, without side effects or traps
+fn is_pure(expr: &Expression) -> bool {
+  match expr {
+    Expression::UnaryOp { op: UnaryOp::FloatToInt, .. } => false,
+    Expression::UnaryOp { arg, .. } => is_pure(arg),
+    Expression::BinaryOp { lhs, rhs, .. } => is_pure(lhs) && is_pure(rhs),
+    Expression::Conditional { cond, then, otherwise } => is_pure(cond) && is_pure(then) && is_pure(otherwise),
+    Expression::LocalGet(_) | Expression::GlobalGet(_) | Expression::NumericLiteral(_) => true,
+    Expression::LocalTee(..) | Expression::FunctionCall(..) | Expression::TableGet(..) => false
+  }
+}
+
 impl From<Function<Statement>> for Function<Instruction> {
   fn from(func: Function<Statement>) -> Self {
     let mut body = Vec::new();
@@ -35,16 +69,19 @@ impl From<Function<Statement>> for Function<Instruction> {
 impl From<Statement> for Vec<Instruction> {
   fn from(stmt: Statement) -> Vec<Instruction> {
     let mut instrs = Vec::new();
-    let mapbody = |body: Vec<Statement>| body.into_iter().map(Vec::<Instruction>::from).flatten().collect();
+    let mapbody = |body: Vec<Statement>| body.into_iter().flat_map(Vec::<Instruction>::from).collect();
     let mut pushexpr = |expr: Expression| instrs.append(&mut expr.into());
     match stmt {
-      Statement::Loop(body) => instrs.push(Instruction::Loop(mapbody(body))),
+      Statement::Loop { body, step } => instrs.push(Instruction::Loop { body: mapbody(body), step: mapbody(step) }),
       Statement::If { cond, then, otherwise } => {
         pushexpr(cond);
         instrs.push(Instruction::If { then: mapbody(then), otherwise: mapbody(otherwise) })
       }
-      Statement::Break => instrs.push(Instruction::Break),
-      Statement::Continue => instrs.push(Instruction::Continue),
+      Statement::Switch { index, targets, cases, default } => {
+        instrs.push(Instruction::Switch { index: index.into(), targets, cases: cases.into_iter().map(mapbody).collect(), default: mapbody(default) })
+      },
+      Statement::Break(depth) => instrs.push(Instruction::Break(depth)),
+      Statement::Continue(depth) => instrs.push(Instruction::Continue(depth)),
       Statement::LocalSet(index, expr) => {
         pushexpr(expr);
         instrs.push(Instruction::LocalSet(index));
@@ -54,7 +91,7 @@ impl From<Statement> for Vec<Instruction> {
         instrs.push(Instruction::GlobalSet(index));
       },
       Statement::Call(index, args) => {
-        args.into_iter().for_each(|expr| pushexpr(expr));
+        args.into_iter().for_each(&mut pushexpr);
         instrs.push(Instruction::Call(index));
         instrs.push(Instruction::Drop()); // Discard return value
       }
@@ -82,11 +119,31 @@ impl From<Expression> for Vec<Instruction> {
         instrs.push(Instruction::BinOp(op));
       }
       Expression::LocalGet(index) => instrs.push(Instruction::LocalGet(index)),
+      Expression::LocalTee(index, value) => {
+        pushexpr(*value);
+        instrs.push(Instruction::LocalTee(index));
+      }
       Expression::GlobalGet(index) => instrs.push(Instruction::GlobalGet(index)),
       Expression::FunctionCall(index, args) => {
-        args.into_iter().for_each(|expr| pushexpr(expr));
+        args.into_iter().for_each(&mut pushexpr);
         instrs.push(Instruction::Call(index));
       }
+      Expression::TableGet(table, index) => {
+        pushexpr(*index);
+        instrs.push(Instruction::TableGet(table));
+      }
+      // Evaluating both values is cheaper than a branch when it can't be told apart.
+      // select takes the condition last, so it can't have effects the values depend on either
+      Expression::Conditional { cond, then, otherwise } if is_pure(&cond) && is_pure(&then) && is_pure(&otherwise) => {
+        pushexpr(*then);
+        pushexpr(*otherwise);
+        pushexpr(*cond);
+        instrs.push(Instruction::Select());
+      }
+      Expression::Conditional { cond, then, otherwise } => {
+        pushexpr(*cond);
+        instrs.push(Instruction::Conditional { then: (*then).into(), otherwise: (*otherwise).into() });
+      }
       Expression::NumericLiteral(literal) => instrs.push(Instruction::Const(literal))
     }
     instrs
@@ -97,7 +154,10 @@ impl AnalysisResults<Instruction> {
   pub fn from_statement_analysis(analysis: AnalysisResults<Statement>) -> Self {
     AnalysisResults {
       global_variables: analysis.global_variables,
+      global_declarations: analysis.global_declarations,
       funcname_map: analysis.funcname_map,
+      tables: analysis.tables,
+      exports: analysis.exports,
       functions: analysis.functions.into_iter().map(|(k, v)| (k, v.into())).collect()
     }
   }
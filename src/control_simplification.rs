@@ -4,16 +4,32 @@ use crate::jsonlang::*;
 
 #[derive(Debug, Clone)]
 pub enum SimplifiedStatement {
-  Loop(Box<SimplifiedStatement>),
+  // Runs the body and then the step until a break, continue skips the rest of the body
+  Loop {
+    body: Box<SimplifiedStatement>,
+    step: Box<SimplifiedStatement>
+  },
   If {
     cond: Expression,
     then: Box<SimplifiedStatement>,
     otherwise: Box<SimplifiedStatement>
   },
-  Break,
-  Continue,
+  // Number of loops around the innermost one that are also left
+  Break(u32),
+  Continue(u32),
+  // Runs the case whose label equals the value, or the default. Labels are constant expressions, they are
+  // only known once variable analysis resolves the constants. The value is a local, it is read more than once
+  Switch {
+    value: String,
+    labels: Vec<Expression>,
+    cases: Vec<SimplifiedStatement>,
+    default: Box<SimplifiedStatement>
+  },
   Declare(String, Expression),
+  Const(String, Expression),
   Set(String, Expression),
+  // Set of a variable that must already exist, with a value computed from it
+  Update(String, Expression),
   Call(String, Vec<Expression>),
   Return(Expression),
   Block(Vec<SimplifiedStatement>)
@@ -25,7 +41,18 @@ pub enum SimplifiedTopStatement {
   DeclarationStatement {
     name: String,
     args: Vec<String>,
-    content: SimplifiedStatement
+    content: SimplifiedStatement,
+    export: Option<Export>
+  },
+  Global {
+    name: String,
+    value: Option<Expression>,
+    mutable: bool,
+    export: Option<Export>
+  },
+  Table {
+    name: String,
+    values: Vec<f64>
   }
 }
 
@@ -33,90 +60,161 @@ pub fn top_simplify_control_structures(program: JsonLang) -> Vec<SimplifiedTopSt
   let mut statements = vec![];
   for statement in program.statements {
     statements.push(match statement {
-      TopStatement::Statement(stmt) => SimplifiedTopStatement::Statement(simplify_control_structures(stmt)),
+      TopStatement::Statement(stmt) => SimplifiedTopStatement::Statement(simplify_control_structures(stmt, &mut Vec::new())),
       TopStatement::DeclarationStatement(decl) => SimplifiedTopStatement::DeclarationStatement {
-        name: decl.function, args: decl.args, content: simplify_control_structures(decl.block)
-      }
+        name: decl.function, args: decl.args, content: simplify_control_structures(decl.block, &mut Vec::new()), export: decl.export
+      },
+      TopStatement::GlobalDeclaration(global) => SimplifiedTopStatement::Global {
+        name: global.global,
+        value: global.value.map(|value| *value),
+        mutable: global.mutable.unwrap_or(true),
+        export: global.export
+      },
+      TopStatement::TableDeclaration(table) => SimplifiedTopStatement::Table { name: table.table, values: table.values },
+      TopStatement::ImportStatement(import) => panic!("Import of {} wasn't resolved", import.import),
+      TopStatement::Directive(_) => panic!("Directives are only allowed in loaded files")
     })
   }
   statements
 }
 
-pub fn simplify_control_structures(statement: Statement) -> SimplifiedStatement {
+// Loops a break or continue with that label leaves, counted from the innermost one
+fn loop_depth(loops: &[Option<String>], label: Option<&String>, statement: &str) -> u32 {
+  let depth = match label {
+    None if loops.is_empty() => panic!("{} outside a loop", statement),
+    None => 0,
+    Some(label) => loops.iter().rev().position(|name| name.as_ref() == Some(label))
+      .unwrap_or_else(|| panic!("{} of {}, which isn't a loop around it", statement, label))
+  };
+  depth as u32
+}
+
+// The body of a loop, with its label available to the breaks and continues inside it
+fn loop_body(statement: Statement, label: &Option<String>, loops: &mut Vec<Option<String>>) -> SimplifiedStatement {
+  loops.push(label.clone());
+  let body = simplify_control_structures(statement, loops);
+  loops.pop();
+  body
+}
+
+// Labels of the loops around the statement, from the outermost
+pub fn simplify_control_structures(statement: Statement, loops: &mut Vec<Option<String>>) -> SimplifiedStatement {
   match statement {
     Statement::If { branches, else_branch } => {
       let mut stmt = if let Some(branch) = else_branch {
-        simplify_control_structures(*branch)
+        simplify_control_structures(*branch, loops)
       } else {
         SimplifiedStatement::Block(vec![])
       };
       for if_case in branches.into_iter().rev() {
         stmt = SimplifiedStatement::If {
           cond: if_case.cond,
-          then: Box::new(simplify_control_structures(*if_case.then)),
+          then: Box::new(simplify_control_structures(*if_case.then, loops)),
           otherwise: Box::new(stmt)
         }
       }
       stmt
     },
-    Statement::While { condition, do_block } => {
-      SimplifiedStatement::Loop(Box::new(SimplifiedStatement::If {
+    Statement::While { condition, do_block, label } => SimplifiedStatement::Loop {
+      body: Box::new(SimplifiedStatement::If {
         cond: *condition,
-        then: Box::new(simplify_control_structures(*do_block)),
-        otherwise: Box::new(SimplifiedStatement::Break)
-      }))
+        then: Box::new(loop_body(*do_block, &label, loops)),
+        otherwise: Box::new(SimplifiedStatement::Break(0))
+      }),
+      step: Box::new(SimplifiedStatement::Block(vec![]))
     },
     Statement::StatementList(stmt_list) => {
       let mut list = vec![];
       for stmt in stmt_list {
-        list.push(simplify_control_structures(stmt))
+        list.push(simplify_control_structures(stmt, loops))
       }
       SimplifiedStatement::Block(list)
     },
-    Statement::Iterator { iterator, from, to, step, do_block } => {
-      SimplifiedStatement::Block(vec![
-        SimplifiedStatement::Declare(iterator.clone(), *from),
-        SimplifiedStatement::Loop(Box::new(SimplifiedStatement::Block(vec![
-          SimplifiedStatement::If {
-            cond: Expression::BinaryOp { lhs: Box::new(Expression::VariableAccess(iterator.clone())), op: BinaryOp::Greater, rhs: to },
-            then: Box::new(SimplifiedStatement::Break),
-            otherwise: Box::new(simplify_control_structures(*do_block))
-          },
-          SimplifiedStatement::Set(iterator.clone(), Expression::BinaryOp {
-            lhs: Box::new(Expression::VariableAccess(iterator.clone())),
-            op: BinaryOp::Addition,
-            rhs: if let Some(step) = step {
-              step
-            } else {
-              Box::new(Expression::NumericLiteral(1.0))
-            }
-          })
-        ])))
-      ])
-    },
-    Statement::Until { do_block, until } => {
-      SimplifiedStatement::Loop(Box::new(
-        SimplifiedStatement::Block(vec![
-          simplify_control_structures(*do_block),
-          SimplifiedStatement::If {
-            cond: *until,
-            then: Box::new(SimplifiedStatement::Break),
-            otherwise: Box::new(SimplifiedStatement::Block(vec![]))
+    Statement::Iterator { iterator, from, to, step, do_block, label } => {
+      let mut block = vec![SimplifiedStatement::Declare(iterator.clone(), *from)];
+      // The bound and the step are evaluated once, before the first iteration
+      let mut evaluate_once = |name: String, value: Expression| if let Expression::NumericLiteral(_) = value {
+        value
+      } else {
+        block.push(SimplifiedStatement::Declare(name.clone(), value));
+        Expression::VariableAccess(name)
+      };
+      let to = evaluate_once(format!("{}#to", iterator), *to);
+      let step = evaluate_once(format!("{}#step", iterator), step.map_or(Expression::NumericLiteral(1.0), |step| *step));
+      let i = || Box::new(Expression::VariableAccess(iterator.clone()));
+      let compare = |op| Expression::BinaryOp { lhs: i(), op, rhs: Box::new(to.clone()) };
+      // Counting down ends below the bound, the direction is only known when running if the step isn't a literal
+      let past_end = match step {
+        Expression::NumericLiteral(step) if step < 0.0 => compare(BinaryOp::Lesser),
+        Expression::NumericLiteral(_) => compare(BinaryOp::Greater),
+        _ => {
+          let step_is = |op| Box::new(Expression::BinaryOp { lhs: Box::new(step.clone()), op, rhs: Box::new(Expression::NumericLiteral(0.0)) });
+          let and = |lhs, rhs| Box::new(Expression::BinaryOp { lhs, op: BinaryOp::LogicalAnd, rhs: Box::new(rhs) });
+          Expression::BinaryOp {
+            lhs: and(step_is(BinaryOp::Lesser), compare(BinaryOp::Lesser)),
+            op: BinaryOp::LogicalOr,
+            rhs: and(step_is(BinaryOp::GreaterEq), compare(BinaryOp::Greater))
           }
-      ])))
+        }
+      };
+      // A continue in the body still increments the iterator
+      block.push(SimplifiedStatement::Loop {
+        body: Box::new(SimplifiedStatement::If {
+          cond: past_end,
+          then: Box::new(SimplifiedStatement::Break(0)),
+          otherwise: Box::new(loop_body(*do_block, &label, loops))
+        }),
+        step: Box::new(SimplifiedStatement::Set(iterator.clone(), Expression::BinaryOp {
+          lhs: i(),
+          op: BinaryOp::Addition,
+          rhs: Box::new(step)
+        }))
+      });
+      SimplifiedStatement::Block(block)
+    },
+    // A continue in the body still checks the condition
+    Statement::Until { do_block, until, label } => SimplifiedStatement::Loop {
+      body: Box::new(loop_body(*do_block, &label, loops)),
+      step: Box::new(SimplifiedStatement::If {
+        cond: *until,
+        then: Box::new(SimplifiedStatement::Break(0)),
+        otherwise: Box::new(SimplifiedStatement::Block(vec![]))
+      })
     },
+    Statement::Switch { value, cases, default } => simplify_switch(*value, cases, default, loops),
+    Statement::Break { label } => SimplifiedStatement::Break(loop_depth(loops, Some(&label), "break")),
+    Statement::Continue { label } => SimplifiedStatement::Continue(loop_depth(loops, Some(&label), "continue")),
     Statement::Other(s) => {
       if s == "break" {
-        SimplifiedStatement::Break
+        SimplifiedStatement::Break(loop_depth(loops, None, "break"))
       } else if s == "continue" {
-        SimplifiedStatement::Continue
+        SimplifiedStatement::Continue(loop_depth(loops, None, "continue"))
       } else {
         panic!("Invalid string element.");
       }
     }
     Statement::Declare { declare, value } => SimplifiedStatement::Declare(declare, *value),
+    Statement::Const { name, value } => SimplifiedStatement::Const(name, *value),
     Statement::Set { set, value } => SimplifiedStatement::Set(set, *value),
+    Statement::Update { name, op, value } => update(name, op.binary_op(), *value),
+    Statement::Increment { increment } => update(increment, BinaryOp::Addition, Expression::NumericLiteral(1.0)),
+    Statement::Decrement { decrement } => update(decrement, BinaryOp::Substraction, Expression::NumericLiteral(1.0)),
     Statement::Call { name, args } => SimplifiedStatement::Call(name, args),
     Statement::Return { return_value } => SimplifiedStatement::Return(*return_value),
   }
-}
\ No newline at end of file
+}
+
+fn update(name: String, op: BinaryOp, value: Expression) -> SimplifiedStatement {
+  let current = Box::new(Expression::VariableAccess(name.clone()));
+  SimplifiedStatement::Update(name, Expression::BinaryOp { lhs: current, op, rhs: Box::new(value) })
+}
+
+// The value is evaluated once into a hidden local, variable analysis picks between a jump table and comparisons.
+// Breaks and continues inside the cases are for the loops around the switch.
+fn simplify_switch(value: Expression, cases: Vec<SwitchCase>, default: Option<Box<Statement>>, loops: &mut Vec<Option<String>>) -> SimplifiedStatement {
+  let name = "switch#value".to_string();
+  let (labels, bodies) = cases.into_iter().map(|case| (case.case, simplify_control_structures(*case.then, loops))).unzip();
+  let default = default.map_or(SimplifiedStatement::Block(vec![]), |stmt| simplify_control_structures(*stmt, loops));
+  let switch = SimplifiedStatement::Switch { value: name.clone(), labels, cases: bodies, default: Box::new(default) };
+  SimplifiedStatement::Block(vec![SimplifiedStatement::Declare(name, value), switch])
+}
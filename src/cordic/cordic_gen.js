@@ -19,22 +19,10 @@ const coefficients = [
 let program = [];
 
 program.push({
-  "function": "cordic_lut",
-  args: ["n"],
-  block:[{
-    "if": coefficients.map((v, i) => ({
-      cond: {binop: "==", argl: "n", argr: i}, "then": [{"return": v}]
-    }))
-  }]
+  "table": "cordic_lut",
+  "values": coefficients
 });
 
-// Dummy
-program.push({
-  "function":	"floor",
-  "args":	["x"],
-  "block":	[]
-})
-
 // Generated with jsl from cordic.lua
 program.push({
   "function":	"ln_cordic",
@@ -106,8 +94,8 @@ program.push({
               "cond":	{
                 "binop":	"<",
                 "argl":	{
-                  "call":	"cordic_lut",
-                  "args":	["i"]
+                  "table":	"cordic_lut",
+                  "index":	"i"
                 },
                 "argr":	"x"
               },
@@ -124,8 +112,8 @@ program.push({
                     "binop":	"/",
                     "argl":	"x",
                     "argr":	{
-                      "call":	"cordic_lut",
-                      "args":	["i"]
+                      "table":	"cordic_lut",
+                      "index":	"i"
                     }
                   }
                 }]
@@ -284,8 +272,8 @@ program.push({
                     "binop":	"*",
                     "argl":	"result",
                     "argr":	{
-                      "call":	"cordic_lut",
-                      "args":	["i"]
+                      "table":	"cordic_lut",
+                      "index":	"i"
                     }
                   }
                 }, {
@@ -357,82 +345,4 @@ program.push({
     }]
 })
 
-program.push({
-  "function":	"pow",
-  "args":	["base", "exponent"],
-  "block":	[{
-      "declare":	"result",
-      "value":	1
-    }, {
-      "while":	{
-        "binop":	">=",
-        "argl":	"exponent",
-        "argr":	1
-      },
-      "do":	[{
-          "set":	"exponent",
-          "value":	{
-            "binop":	"-",
-            "argl":	"exponent",
-            "argr":	1
-          }
-        }, {
-          "set":	"result",
-          "value":	{
-            "binop":	"*",
-            "argl":	"result",
-            "argr":	"base"
-          }
-        }]
-    }, {
-      "while":	{
-        "binop":	"<",
-        "argl":	"exponent",
-        "argr":	0
-      },
-      "do":	[{
-          "set":	"exponent",
-          "value":	{
-            "binop":	"+",
-            "argl":	"exponent",
-            "argr":	1
-          }
-        }, {
-          "set":	"result",
-          "value":	{
-            "binop":	"/",
-            "argl":	"result",
-            "argr":	"base"
-          }
-        }]
-    }, {
-      "if":	[{
-          "cond":	{
-            "binop":	"~=",
-            "argl":	"exponent",
-            "argr":	0
-          },
-          "then":	[{
-              "return":	{
-                "binop":	"*",
-                "argl":	"result",
-                "argr":	{
-                  "call":	"exp_cordic",
-                  "args":	[{
-                      "binop":	"*",
-                      "argl":	{
-                        "call":	"ln_cordic",
-                        "args":	["base"]
-                      },
-                      "argr":	"exponent"
-                    }]
-                }
-              }
-            }]
-        }]
-    }, {
-      "return":	"result"
-    }]
-});
-
 fs.writeFileSync("cordic.lang.json", JSON.stringify(program, null, 1));
\ No newline at end of file
@@ -0,0 +1,333 @@
+use std::collections::{ HashMap, HashSet };
+
+use crate::jsonlang::Expression;
+use crate::control_simplification::{ SimplifiedStatement, SimplifiedTopStatement };
+
+// Globals that are definitely set at a point of the top level code, None where it can't be reached
+type Assigned = Option<HashSet<String>>;
+
+fn join(a: Assigned, b: Assigned) -> Assigned {
+  match (a, b) {
+    (Some(a), Some(b)) => Some(a.intersection(&b).cloned().collect()),
+    (a, None) => a,
+    (None, b) => b
+  }
+}
+
+// Names visible in the blocks around a statement, with what a pass keeps about each one.
+// The passes that look at the simplified program resolve names through it.
+pub struct Scopes<T = ()>(Vec<HashMap<String, T>>);
+
+impl<T: Clone> Scopes<T> {
+  pub fn new(names: &[String], value: T) -> Self {
+    Scopes(vec![names.iter().map(|name| (name.clone(), value.clone())).collect()])
+  }
+  pub fn has(&self, name: &String) -> bool {
+    self.0.iter().any(|scope| scope.contains_key(name))
+  }
+  // Innermost declaration of a name
+  pub fn get_mut(&mut self, name: &String) -> Option<&mut T> {
+    self.0.iter_mut().rev().find_map(|scope| scope.get_mut(name))
+  }
+  // Declares a name in the innermost block, returns the declaration it replaces in that same block
+  pub fn declare(&mut self, name: &str, value: T) -> Option<T> {
+    self.0.last_mut().unwrap().insert(name.to_string(), value)
+  }
+  pub fn enter_block(&mut self) {
+    self.0.push(HashMap::new());
+  }
+  // Names declared in the innermost block
+  pub fn exit_block(&mut self) -> HashMap<String, T> {
+    self.0.pop().unwrap()
+  }
+}
+
+// Globals a function, or the top level code, may set
+#[derive(Default)]
+struct Effects {
+  sets: HashSet<String>
+}
+
+impl Effects {
+  fn statement(&mut self, stmt: &SimplifiedStatement, scopes: &mut Scopes) {
+    match stmt {
+      SimplifiedStatement::Loop { body, step } => {
+        self.statement(body, scopes);
+        self.statement(step, scopes);
+      },
+      SimplifiedStatement::If { cond, then, otherwise } => {
+        self.expression(cond);
+        self.statement(then, scopes);
+        self.statement(otherwise, scopes);
+      },
+      SimplifiedStatement::Switch { labels, cases, default, .. } => {
+        labels.iter().for_each(|label| self.expression(label));
+        cases.iter().chain([&**default]).for_each(|stmt| self.statement(stmt, scopes));
+      },
+      SimplifiedStatement::Break(_) | SimplifiedStatement::Continue(_) => (),
+      SimplifiedStatement::Declare(name, value) | SimplifiedStatement::Const(name, value) => {
+        self.expression(value);
+        scopes.declare(name, ());
+      },
+      SimplifiedStatement::Set(name, value) => {
+        self.expression(value);
+        if !scopes.has(name) {
+          self.sets.insert(name.clone());
+        }
+      },
+      // Updates don't create globals, the read of the current value is checked like any other
+      SimplifiedStatement::Update(_, value) => self.expression(value),
+      SimplifiedStatement::Call(_, args) => args.iter().for_each(|arg| self.expression(arg)),
+      SimplifiedStatement::Return(value) => self.expression(value),
+      SimplifiedStatement::Block(block) => {
+        scopes.enter_block();
+        block.iter().for_each(|stmt| self.statement(stmt, scopes));
+        scopes.exit_block();
+      }
+    }
+  }
+  fn expression(&mut self, expr: &Expression) {
+    match expr {
+      Expression::UnaryOp { arg, .. } => self.expression(arg),
+      Expression::BinaryOp { lhs, rhs, .. } => {
+        self.expression(lhs);
+        self.expression(rhs);
+      },
+      Expression::FunctionCall { args, .. } => args.iter().for_each(|arg| self.expression(arg)),
+      Expression::TableAccess { index, .. } => self.expression(index),
+      Expression::TableFunction { .. } => (),
+      Expression::Conditional { cond, then, otherwise } => [cond, then, otherwise].into_iter().for_each(|expr| self.expression(expr)),
+      Expression::VariableAccess(_) | Expression::NumericLiteral(_) => ()
+    }
+  }
+}
+
+struct Checker {
+  // Globals with a value from the start, declared ones and constants
+  initialized: HashSet<String>,
+  // Globals that are 0 until they are set, implicit ones and top level declarations
+  assignable: HashSet<String>,
+  // Globals each function sets on every path, directly or through the functions it calls
+  function_sets: HashMap<String, HashSet<String>>,
+  // Whether reads of assignable globals are checked, only the top level code runs in a known order
+  top_level: bool,
+  // Function and statement being checked, for diagnostics
+  location: String,
+  statement: String,
+  // States at the breaks and continues of each enclosing loop, and at the returns of the function
+  breaks: Vec<Assigned>,
+  continues: Vec<Assigned>,
+  returns: Assigned,
+  diagnostics: Vec<String>
+}
+
+impl Checker {
+  // Globals set on every path through each function. Starts from none and grows until it is stable,
+  // so a recursive call only counts what the function sets on every path that doesn't recurse
+  fn find_function_sets(&mut self, functions: &[(&String, &Vec<String>, &SimplifiedStatement)]) {
+    loop {
+      let mut function_sets = HashMap::new();
+      for (name, args, content) in functions {
+        self.returns = None;
+        let end = self.statement(content, &mut Scopes::new(args, ()), Some(HashSet::new()));
+        function_sets.insert((*name).clone(), join(end, self.returns.take()).unwrap_or_default());
+      }
+      if function_sets == self.function_sets {
+        break
+      }
+      self.function_sets = function_sets;
+    }
+    self.diagnostics.clear();
+  }
+  fn read(&mut self, name: &String, scopes: &Scopes, assigned: &Assigned) {
+    if scopes.has(name) || self.initialized.contains(name) {
+      return
+    }
+    if !self.assignable.contains(name) {
+      self.diagnostics.push(format!("{} isn't defined, read by {} in {}", name, self.statement, self.location))
+    } else if let (true, Some(assigned)) = (self.top_level, assigned) {
+      if !assigned.contains(name) {
+        self.diagnostics.push(format!("{} may be read before it is set, by {} in {}", name, self.statement, self.location))
+      }
+    }
+  }
+  fn call(&self, name: &String, assigned: &mut Assigned) {
+    if let (Some(assigned), Some(sets)) = (assigned, self.function_sets.get(name)) {
+      assigned.extend(sets.iter().cloned());
+    }
+  }
+  fn expression(&mut self, expr: &Expression, scopes: &Scopes, assigned: &mut Assigned) {
+    match expr {
+      Expression::UnaryOp { arg, .. } => self.expression(arg, scopes, assigned),
+      Expression::BinaryOp { lhs, rhs, .. } => {
+        self.expression(lhs, scopes, assigned);
+        self.expression(rhs, scopes, assigned);
+      },
+      Expression::VariableAccess(name) => self.read(name, scopes, assigned),
+      Expression::FunctionCall { name, args } => {
+        args.iter().for_each(|arg| self.expression(arg, scopes, assigned));
+        self.call(name, assigned);
+      },
+      Expression::TableAccess { index, .. } => self.expression(index, scopes, assigned),
+      Expression::TableFunction { .. } => (),
+      // Only one of the values is evaluated, like the branches of an if
+      Expression::Conditional { cond, then, otherwise } => {
+        self.expression(cond, scopes, assigned);
+        let mut then_assigned = assigned.clone();
+        self.expression(then, scopes, &mut then_assigned);
+        self.expression(otherwise, scopes, assigned);
+        *assigned = join(then_assigned, assigned.take());
+      },
+      Expression::NumericLiteral(_) => ()
+    }
+  }
+  fn statement(&mut self, stmt: &SimplifiedStatement, scopes: &mut Scopes, assigned: Assigned) -> Assigned {
+    match stmt {
+      // Loops only end through a break, their body runs at least once and the step runs after it or a continue
+      SimplifiedStatement::Loop { body, step } => {
+        self.breaks.push(None);
+        self.continues.push(None);
+        let assigned = self.statement(body, scopes, assigned);
+        let assigned = join(assigned, self.continues.pop().unwrap());
+        self.statement(step, scopes, assigned);
+        self.breaks.pop().unwrap()
+      },
+      SimplifiedStatement::If { cond, then, otherwise } => {
+        let mut assigned = assigned;
+        self.statement = "a condition".to_string();
+        self.expression(cond, scopes, &mut assigned);
+        let then = self.statement(then, scopes, assigned.clone());
+        let otherwise = self.statement(otherwise, scopes, assigned);
+        join(then, otherwise)
+      },
+      // The value is a local that was just declared
+      SimplifiedStatement::Switch { labels, cases, default, .. } => {
+        let mut assigned = assigned;
+        self.statement = "a switch case".to_string();
+        labels.iter().for_each(|label| self.expression(label, scopes, &mut assigned));
+        cases.iter().chain([&**default]).fold(None, |joined, stmt| join(joined, self.statement(stmt, scopes, assigned.clone())))
+      },
+      SimplifiedStatement::Break(depth) => {
+        let index = self.breaks.len() - 1 - *depth as usize;
+        self.breaks[index] = join(self.breaks[index].take(), assigned);
+        None
+      },
+      SimplifiedStatement::Continue(depth) => {
+        let index = self.continues.len() - 1 - *depth as usize;
+        self.continues[index] = join(self.continues[index].take(), assigned);
+        None
+      },
+      SimplifiedStatement::Declare(name, value) | SimplifiedStatement::Const(name, value) => {
+        let mut assigned = assigned;
+        self.statement = format!("the declaration of {}", name);
+        self.expression(value, scopes, &mut assigned);
+        scopes.declare(name, ());
+        assigned
+      },
+      SimplifiedStatement::Update(name, value) => {
+        let mut assigned = assigned;
+        self.statement = format!("the update of {}", name);
+        self.expression(value, scopes, &mut assigned);
+        assigned
+      },
+      SimplifiedStatement::Set(name, value) => {
+        let mut assigned = assigned;
+        self.statement = format!("set {}", name);
+        self.expression(value, scopes, &mut assigned);
+        if let (false, Some(assigned)) = (scopes.has(name), &mut assigned) {
+          assigned.insert(name.clone());
+        }
+        assigned
+      },
+      SimplifiedStatement::Call(name, args) => {
+        let mut assigned = assigned;
+        self.statement = format!("the call to {}", name);
+        args.iter().for_each(|arg| self.expression(arg, scopes, &mut assigned));
+        self.call(name, &mut assigned);
+        assigned
+      },
+      SimplifiedStatement::Return(value) => {
+        let mut assigned = assigned;
+        self.statement = "a return".to_string();
+        self.expression(value, scopes, &mut assigned);
+        self.returns = join(self.returns.take(), assigned);
+        None
+      },
+      SimplifiedStatement::Block(block) => {
+        scopes.enter_block();
+        let assigned = block.iter().fold(assigned, |assigned, stmt| self.statement(stmt, scopes, assigned));
+        scopes.exit_block();
+        assigned
+      }
+    }
+  }
+}
+
+// Reports every read of a name that isn't defined anywhere and every read in the top level code
+// of a global that isn't set on all the paths leading to it, calls count as setting what the
+// function sets on all its paths. Reads in functions aren't checked against sets, they can run at any time.
+pub fn check_assignments(program: &[SimplifiedTopStatement]) {
+  let mut initialized = HashSet::new();
+  let mut function_effects = Vec::new();
+  let mut top_effects = Effects::default();
+  let mut top_scopes = Scopes::new(&[], ());
+  for statement in program {
+    match statement {
+      SimplifiedTopStatement::Statement(SimplifiedStatement::Declare(name, _)) => {
+        top_effects.sets.insert(name.clone());
+      },
+      SimplifiedTopStatement::Statement(SimplifiedStatement::Const(name, _)) => {
+        initialized.insert(name.clone());
+      },
+      SimplifiedTopStatement::Statement(stmt) => top_effects.statement(stmt, &mut top_scopes),
+      SimplifiedTopStatement::DeclarationStatement { args, content, .. } => {
+        let mut effects = Effects::default();
+        effects.statement(content, &mut Scopes::new(args, ()));
+        function_effects.push(effects);
+      },
+      SimplifiedTopStatement::Global { name, .. } => {
+        initialized.insert(name.clone());
+      },
+      SimplifiedTopStatement::Table { .. } => ()
+    }
+  }
+  let mut assignable: HashSet<String> = top_effects.sets;
+  function_effects.into_iter().for_each(|effects| assignable.extend(effects.sets));
+  let mut checker = Checker {
+    initialized, assignable, function_sets: HashMap::new(), top_level: false, location: String::new(),
+    statement: String::new(), breaks: Vec::new(), continues: Vec::new(), returns: None, diagnostics: Vec::new()
+  };
+  let functions: Vec<_> = program.iter().filter_map(|statement| match statement {
+    SimplifiedTopStatement::DeclarationStatement { name, args, content, .. } => Some((name, args, content)),
+    _ => None
+  }).collect();
+  checker.find_function_sets(&functions);
+  checker.top_level = true;
+  checker.location = "the top level code".to_string();
+  let mut top_scopes = Scopes::new(&[], ());
+  let mut assigned = Some(HashSet::new());
+  for statement in program {
+    match statement {
+      SimplifiedTopStatement::Statement(SimplifiedStatement::Declare(name, value)) => {
+        checker.statement = format!("the declaration of {}", name);
+        checker.expression(value, &top_scopes, &mut assigned);
+        if let Some(assigned) = &mut assigned {
+          assigned.insert(name.clone());
+        }
+      },
+      SimplifiedTopStatement::Statement(SimplifiedStatement::Const(..)) => (),
+      SimplifiedTopStatement::Statement(stmt) => {
+        assigned = checker.statement(stmt, &mut top_scopes, assigned);
+      },
+      _ => ()
+    }
+  }
+  checker.top_level = false;
+  for (name, args, content) in functions {
+    checker.location = format!("function {}", name);
+    checker.statement(content, &mut Scopes::new(args, ()), Some(HashSet::new()));
+  }
+  if !checker.diagnostics.is_empty() {
+    panic!("{}", checker.diagnostics.join("\n"))
+  }
+}
@@ -19,11 +19,23 @@ fn demand_int(expr: Expression) -> Expression {
       op: UnaryOp::IntToFloat, arg: Box::new(demand_int(*arg))
     }),
     Expression::UnaryOp { op: UnaryOp::Sqrt, arg } => into_int(Expression::UnaryOp {
-      op: UnaryOp::NumericNegation, arg: Box::new(demand_float(*arg))
+      op: UnaryOp::Sqrt, arg: Box::new(demand_float(*arg))
     }),
     Expression::UnaryOp { op: UnaryOp::Floor, arg } => into_int(Expression::UnaryOp {
       op: UnaryOp::Floor, arg: Box::new(demand_float(*arg))
     }),
+    Expression::UnaryOp { op: UnaryOp::Ceil, arg } => into_int(Expression::UnaryOp {
+      op: UnaryOp::Ceil, arg: Box::new(demand_float(*arg))
+    }),
+    Expression::UnaryOp { op: UnaryOp::Trunc, arg } => into_int(Expression::UnaryOp {
+      op: UnaryOp::Trunc, arg: Box::new(demand_float(*arg))
+    }),
+    Expression::UnaryOp { op: UnaryOp::Nearest, arg } => into_int(Expression::UnaryOp {
+      op: UnaryOp::Nearest, arg: Box::new(demand_float(*arg))
+    }),
+    Expression::UnaryOp { op: UnaryOp::Abs, arg } => into_int(Expression::UnaryOp {
+      op: UnaryOp::Abs, arg: Box::new(demand_float(*arg))
+    }),
     Expression::BinaryOp { op: BinaryOp::Lesser, .. } => floatop(expr),
     Expression::BinaryOp { op: BinaryOp::LessEq, .. } => floatop(expr),
     Expression::BinaryOp { op: BinaryOp::Greater, .. } => floatop(expr),
@@ -34,15 +46,17 @@ fn demand_int(expr: Expression) -> Expression {
     Expression::BinaryOp { op: BinaryOp::BitwiseOr, .. } => binop_into_int(expr),
     Expression::BinaryOp { op: BinaryOp::RightShift, .. } => binop_into_int(expr),
     Expression::BinaryOp { op: BinaryOp::LeftShift, .. } => binop_into_int(expr),
-    Expression::BinaryOp { op: BinaryOp::Modulo, .. } => binop_into_int(expr),
     Expression::BinaryOp { lhs, op, rhs } => into_int(Expression::BinaryOp {
       lhs: Box::new(demand_float(*lhs)),
-      op: op,
+      op,
       rhs: Box::new(demand_float(*rhs))
     }),
+    Expression::BinaryIntrinsic { .. } => into_int(demand_float(expr)),
     Expression::LocalGet(_) => into_int(expr),
     Expression::GlobalGet(_) => into_int(expr),
     Expression::FunctionCall(index, exprs) => into_int(Expression::FunctionCall(index, exprs.into_iter().map(demand_float).collect())),
+    Expression::TableGet(table, index) => into_int(Expression::TableGet(table, Box::new(demand_int(*index)))),
+    Expression::Conditional { .. } => into_int(demand_float(expr)),
     Expression::NumericLiteral(_) => into_int(expr)
   }
 }
@@ -105,8 +119,19 @@ fn demand_float(expr: Expression) -> Expression {
     Expression::UnaryOp { op: UnaryOp::Floor, arg } => Expression::UnaryOp {
       op: UnaryOp::Floor, arg: Box::new(demand_float(*arg))
     },
+    Expression::UnaryOp { op: UnaryOp::Ceil, arg } => Expression::UnaryOp {
+      op: UnaryOp::Ceil, arg: Box::new(demand_float(*arg))
+    },
+    Expression::UnaryOp { op: UnaryOp::Trunc, arg } => Expression::UnaryOp {
+      op: UnaryOp::Trunc, arg: Box::new(demand_float(*arg))
+    },
+    Expression::UnaryOp { op: UnaryOp::Nearest, arg } => Expression::UnaryOp {
+      op: UnaryOp::Nearest, arg: Box::new(demand_float(*arg))
+    },
+    Expression::UnaryOp { op: UnaryOp::Abs, arg } => Expression::UnaryOp {
+      op: UnaryOp::Abs, arg: Box::new(demand_float(*arg))
+    },
     Expression::BinaryOp { op: BinaryOp::Exponentiation, .. } => floatop(expr),
-    Expression::BinaryOp { op: BinaryOp::Modulo, .. } => binop_into_float(expr),
     Expression::BinaryOp { op: BinaryOp::BitwiseAnd, .. } => binop_into_float(expr),
     Expression::BinaryOp { op: BinaryOp::BitwiseOr, .. } => binop_into_float(expr),
     Expression::BinaryOp { op: BinaryOp::RightShift, .. } => binop_into_float(expr),
@@ -121,12 +146,23 @@ fn demand_float(expr: Expression) -> Expression {
     Expression::BinaryOp { op: BinaryOp::NotEqual, .. } => into_float(floatop(expr)),
     Expression::BinaryOp { lhs, op, rhs } => Expression::BinaryOp {
       lhs: Box::new(demand_float(*lhs)),
-      op: op,
+      op,
+      rhs: Box::new(demand_float(*rhs))
+    },
+    Expression::BinaryIntrinsic { lhs, op, rhs } => Expression::BinaryIntrinsic {
+      lhs: Box::new(demand_float(*lhs)),
+      op,
       rhs: Box::new(demand_float(*rhs))
     },
     Expression::LocalGet(_) => expr,
     Expression::GlobalGet(_) => expr,
     Expression::FunctionCall(index, exprs) => Expression::FunctionCall(index, exprs.into_iter().map(demand_float).collect()),
+    Expression::TableGet(table, index) => Expression::TableGet(table, Box::new(demand_int(*index))),
+    Expression::Conditional { cond, then, otherwise } => Expression::Conditional {
+      cond: Box::new(demand_int(*cond)),
+      then: Box::new(demand_float(*then)),
+      otherwise: Box::new(demand_float(*otherwise))
+    },
     Expression::NumericLiteral(_) => expr
   }
 }
@@ -134,7 +170,13 @@ fn demand_float(expr: Expression) -> Expression {
 fn fix_statement(stmt: Statement) -> Statement {
   let fix_vec = |list: Vec<Statement>| list.into_iter().map(fix_statement).collect();
   match stmt {
-    Statement::Loop(body) => Statement::Loop(fix_vec(body)),
+    Statement::Loop { body, step } => Statement::Loop { body: fix_vec(body), step: fix_vec(step) },
+    Statement::Switch { index, targets, cases, default } => Statement::Switch {
+      index: demand_int(index),
+      targets,
+      cases: cases.into_iter().map(fix_vec).collect(),
+      default: fix_vec(default)
+    },
     Statement::If { cond, then, otherwise } => Statement::If {
       cond: demand_int(cond),
       then: fix_vec(then),
@@ -144,20 +186,23 @@ fn fix_statement(stmt: Statement) -> Statement {
     Statement::GlobalSet(index, expr) => Statement::GlobalSet(index, demand_float(expr)),
     Statement::Call(index, exprs) => Statement::Call(index, exprs.into_iter().map(demand_float).collect()),
     Statement::Return(expr) => Statement::Return(demand_float(expr)),
-    Statement::Break => Statement::Break,
-    Statement::Continue => Statement::Continue
+    Statement::Break(depth) => Statement::Break(depth),
+    Statement::Continue(depth) => Statement::Continue(depth)
   }
 }
 
 pub fn fix_types(program: AnalysisResults<Statement>) -> AnalysisResults<Statement> {
   AnalysisResults {
     global_variables: program.global_variables,
+    global_declarations: program.global_declarations,
     funcname_map: program.funcname_map,
+    tables: program.tables,
+    exports: program.exports,
     functions: program.functions.into_iter().map(|(index, func)| {
       (index, Function {
         arguments: func.arguments,
         local_count: func.local_count,
-        body: func.body.into_iter().map(|stmt| fix_statement(stmt)).collect()
+        body: func.body.into_iter().map(fix_statement).collect()
       })
     }).collect()
   }
@@ -0,0 +1,194 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::jsonlang::JsonLang;
+use crate::variable_analysis::{ AnalysisResults, UnaryOp };
+use crate::polyfill_ops::{ Statement, Expression, BinaryOp };
+use crate::control_simplification::top_simplify_control_structures;
+use crate::variable_analysis::top_analyze_variables;
+use crate::fixup_types::fix_types;
+use crate::polyfill_ops::polyfill_ops;
+use crate::options::CompilerOptions;
+
+// Runs a program right after polyfill_ops with the same semantics as the emitted wasm.
+// i32 values are kept as f64 holding their unsigned value.
+pub struct Interpreter {
+  program: AnalysisResults<Statement>,
+  globals: RefCell<HashMap<u32, f64>>
+}
+
+// Break and continue count the loops left to go through
+enum Flow {
+  Next,
+  Break(u32),
+  Continue(u32),
+  Return(f64)
+}
+
+fn int(value: f64) -> u32 {
+  value as u32
+}
+
+fn boolean(value: bool) -> f64 {
+  if value { 1.0 } else { 0.0 }
+}
+
+impl Interpreter {
+  pub fn compile(source: &str, options: &CompilerOptions) -> Self {
+    let p: JsonLang = serde_json::from_str(source).unwrap();
+    let program = polyfill_ops(fix_types(top_analyze_variables(top_simplify_control_structures(p))), options);
+    Interpreter { program, globals: RefCell::new(HashMap::new()) }
+  }
+
+  pub fn call(&self, name: &str, args: &[f64]) -> f64 {
+    let index = *self.program.funcname_map.get(name).unwrap_or_else(|| panic!("Unknown function {}", name));
+    self.call_index(index, args.to_vec())
+  }
+
+  fn call_index(&self, index: u32, args: Vec<f64>) -> f64 {
+    let func = self.program.functions.get(&index).unwrap();
+    let mut locals = args;
+    locals.resize((func.arguments + func.local_count) as usize, 0.0);
+    match self.run_block(&func.body, &mut locals) {
+      Flow::Return(value) => value,
+      _ => 0.0
+    }
+  }
+
+  fn run_block(&self, block: &[Statement], locals: &mut Vec<f64>) -> Flow {
+    for stmt in block {
+      match self.run_statement(stmt, locals) {
+        Flow::Next => {},
+        flow => return flow
+      }
+    }
+    Flow::Next
+  }
+
+  fn run_statement(&self, stmt: &Statement, locals: &mut Vec<f64>) -> Flow {
+    match stmt {
+      Statement::Loop { body, step } => loop {
+        for block in [body, step] {
+          match self.run_block(block, locals) {
+            Flow::Break(0) => return Flow::Next,
+            Flow::Break(depth) => return Flow::Break(depth - 1),
+            Flow::Continue(0) | Flow::Next => {},
+            Flow::Continue(depth) => return Flow::Continue(depth - 1),
+            Flow::Return(value) => return Flow::Return(value)
+          }
+        }
+      },
+      Statement::If { cond, then, otherwise } => {
+        if self.eval(cond, locals) != 0.0 {
+          self.run_block(then, locals)
+        } else {
+          self.run_block(otherwise, locals)
+        }
+      },
+      Statement::Switch { index, targets, cases, default } => {
+        match targets.get(int(self.eval(index, locals)) as usize).and_then(|target| cases.get(*target as usize)) {
+          Some(case) => self.run_block(case, locals),
+          None => self.run_block(default, locals)
+        }
+      },
+      Statement::Break(depth) => Flow::Break(*depth),
+      Statement::Continue(depth) => Flow::Continue(*depth),
+      Statement::LocalSet(index, expr) => {
+        locals[*index as usize] = self.eval(expr, locals);
+        Flow::Next
+      },
+      Statement::GlobalSet(index, expr) => {
+        let value = self.eval(expr, locals);
+        self.globals.borrow_mut().insert(*index, value);
+        Flow::Next
+      },
+      Statement::Call(index, args) => {
+        let args = args.iter().map(|arg| self.eval(arg, locals)).collect();
+        self.call_index(*index, args);
+        Flow::Next
+      },
+      Statement::Return(expr) => Flow::Return(self.eval(expr, locals))
+    }
+  }
+
+  fn eval(&self, expr: &Expression, locals: &mut Vec<f64>) -> f64 {
+    match expr {
+      Expression::NumericLiteral(value) => *value,
+      Expression::LocalGet(index) => locals[*index as usize],
+      Expression::LocalTee(index, value) => {
+        locals[*index as usize] = self.eval(value, locals);
+        locals[*index as usize]
+      },
+      Expression::GlobalGet(index) => *self.globals.borrow().get(index)
+        .unwrap_or(&self.program.global_declarations.get(index).map_or(0.0, |global| global.value)),
+      Expression::FunctionCall(index, args) => {
+        let args = args.iter().map(|arg| self.eval(arg, locals)).collect();
+        self.call_index(*index, args)
+      },
+      Expression::TableGet(table, index) => {
+        let index = self.eval(index, locals) as usize;
+        self.program.tables[*table as usize].values[index]
+      },
+      Expression::Conditional { cond, then, otherwise } => if self.eval(cond, locals) != 0.0 {
+        self.eval(then, locals)
+      } else {
+        self.eval(otherwise, locals)
+      },
+      Expression::UnaryOp { op, arg } => {
+        let arg = self.eval(arg, locals);
+        match op {
+          UnaryOp::NumericNegation => -arg,
+          UnaryOp::LogicNegation => boolean(arg == 0.0),
+          UnaryOp::BitwiseNegation => (int(arg) ^ 0x7fffffff) as f64,
+          UnaryOp::FloatToInt => {
+            if !(arg > -1.0 && arg < 4294967296.0) {
+              panic!("Trap: {} doesn't fit in an i32", arg)
+            }
+            arg.trunc()
+          },
+          UnaryOp::IntToFloat => arg,
+          UnaryOp::Sqrt => arg.sqrt(),
+          UnaryOp::Floor => arg.floor(),
+          UnaryOp::Ceil => arg.ceil(),
+          UnaryOp::Trunc => arg.trunc(),
+          UnaryOp::Nearest => {
+            let rounded = arg.round();
+            if (rounded - arg).abs() == 0.5 { 2.0 * (arg / 2.0).round() } else { rounded }
+          },
+          UnaryOp::Abs => arg.abs()
+        }
+      },
+      Expression::BinaryOp { lhs, op, rhs } => {
+        let lhs = self.eval(lhs, locals);
+        let rhs = self.eval(rhs, locals);
+        match op {
+          BinaryOp::Addition => lhs + rhs,
+          BinaryOp::Substraction => lhs - rhs,
+          BinaryOp::Multiplication => lhs * rhs,
+          BinaryOp::Division => lhs / rhs,
+          BinaryOp::IntAddition => int(lhs).wrapping_add(int(rhs)) as f64,
+          BinaryOp::IntSubstraction => int(lhs).wrapping_sub(int(rhs)) as f64,
+          BinaryOp::IntMultiplication => int(lhs).wrapping_mul(int(rhs)) as f64,
+          BinaryOp::IntDivision => (int(lhs) / int(rhs)) as f64,
+          BinaryOp::BitwiseAnd => (int(lhs) & int(rhs)) as f64,
+          BinaryOp::BitwiseOr => (int(lhs) | int(rhs)) as f64,
+          BinaryOp::RightShift => (int(lhs) >> (int(rhs) % 32)) as f64,
+          BinaryOp::LeftShift => (int(lhs) << (int(rhs) % 32)) as f64,
+          BinaryOp::Lesser => boolean(lhs < rhs),
+          BinaryOp::LessEq => boolean(lhs <= rhs),
+          BinaryOp::Greater => boolean(lhs > rhs),
+          BinaryOp::GreaterEq => boolean(lhs >= rhs),
+          BinaryOp::Equal => boolean(lhs == rhs),
+          BinaryOp::NotEqual => boolean(lhs != rhs),
+          BinaryOp::Min => if lhs.is_nan() || rhs.is_nan() { f64::NAN } else if lhs == rhs && lhs == 0.0 {
+            if lhs.is_sign_negative() { lhs } else { rhs }
+          } else { lhs.min(rhs) },
+          BinaryOp::Max => if lhs.is_nan() || rhs.is_nan() { f64::NAN } else if lhs == rhs && lhs == 0.0 {
+            if lhs.is_sign_positive() { lhs } else { rhs }
+          } else { lhs.max(rhs) },
+          BinaryOp::CopySign => lhs.copysign(rhs)
+        }
+      }
+    }
+  }
+}
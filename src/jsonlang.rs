@@ -1,7 +1,9 @@
+use std::fmt;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(untagged)]
+#[allow(clippy::enum_variant_names)]
 pub enum Statement{
   If {
     #[serde(rename = "if")]
@@ -13,7 +15,8 @@ pub enum Statement{
     #[serde(rename = "while")]
     condition: Box<Expression>,
     #[serde(rename = "do")]
-    do_block: Box<Statement>
+    do_block: Box<Statement>,
+    label: Option<String>
   },
   StatementList(Vec<Statement>),
   Iterator {
@@ -22,15 +25,32 @@ pub enum Statement{
     to: Box<Expression>,
     step: Option<Box<Expression>>,
     #[serde(rename = "do")]
-    do_block: Box<Statement>
+    do_block: Box<Statement>,
+    label: Option<String>
   },
   Until {
     #[serde(rename = "do")]
     do_block: Box<Statement>,
-    until: Box<Expression>
+    until: Box<Expression>,
+    label: Option<String>
   },
   Declare { declare: String, value: Box<Expression> },
+  // Replaced by its value, which must be known at compile time
+  Const {
+    #[serde(rename = "const")]
+    name: String,
+    value: Box<Expression>
+  },
   Set { set: String, value: Box<Expression> },
+  // Compound assignment, the variable must already exist
+  Update {
+    #[serde(rename = "update")]
+    name: String,
+    op: AssignOp,
+    value: Box<Expression>
+  },
+  Increment { increment: String },
+  Decrement { decrement: String },
   Call { 
     #[serde(rename = "call")]
     name: String,
@@ -40,9 +60,33 @@ pub enum Statement{
     #[serde(rename = "return")]
     return_value: Box<Expression>
   },
+  // Runs the case with the value of the expression, or the default if none has it
+  Switch {
+    #[serde(rename = "switch")]
+    value: Box<Expression>,
+    cases: Vec<SwitchCase>,
+    default: Option<Box<Statement>>
+  },
+  // Break and continue of the loop with that label, "break" and "continue" are for the innermost loop
+  Break {
+    #[serde(rename = "break")]
+    label: String
+  },
+  Continue {
+    #[serde(rename = "continue")]
+    label: String
+  },
   Other(String)
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SwitchCase {
+  // A constant, numbers and names declared with const
+  pub case: Expression,
+  #[serde(rename = "do")]
+  pub then: Box<Statement>
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct IfConditions {
   pub cond: Expression,
@@ -50,6 +94,45 @@ pub struct IfConditions {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum AssignOp {
+  #[serde(rename = "+=")]
+  Addition,
+  #[serde(rename = "-=")]
+  Substraction,
+  #[serde(rename = "*=")]
+  Multiplication,
+  #[serde(rename = "/=")]
+  Division,
+  #[serde(rename = "%=")]
+  Modulo,
+  #[serde(rename = "&=")]
+  BitwiseAnd,
+  #[serde(rename = "|=")]
+  BitwiseOr,
+  #[serde(rename = ">>=")]
+  RightShift,
+  #[serde(rename = "<<=")]
+  LeftShift
+}
+
+impl AssignOp {
+  pub fn binary_op(&self) -> BinaryOp {
+    match self {
+      AssignOp::Addition => BinaryOp::Addition,
+      AssignOp::Substraction => BinaryOp::Substraction,
+      AssignOp::Multiplication => BinaryOp::Multiplication,
+      AssignOp::Division => BinaryOp::Division,
+      AssignOp::Modulo => BinaryOp::Modulo,
+      AssignOp::BitwiseAnd => BinaryOp::BitwiseAnd,
+      AssignOp::BitwiseOr => BinaryOp::BitwiseOr,
+      AssignOp::RightShift => BinaryOp::RightShift,
+      AssignOp::LeftShift => BinaryOp::LeftShift
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum UnaryOp {
   #[serde(rename = "-")]
   NumericNegation,
@@ -121,21 +204,105 @@ pub enum Expression {
     name: String,
     args: Vec<Expression>
   },
+  // Function of the values of a whole table, like table_sum, which is known at compile time
+  TableFunction {
+    #[serde(rename = "call")]
+    name: String,
+    table: String
+  },
+  TableAccess {
+    table: String,
+    index: Box<Expression>
+  },
+  // The value of then if cond isn't 0, only the chosen one is evaluated
+  Conditional {
+    cond: Box<Expression>,
+    then: Box<Expression>,
+    #[serde(rename = "else")]
+    otherwise: Box<Expression>
+  },
   NumericLiteral (f64)
 }
 
+// Operators are written as the source spells them
+fn symbol<T: Serialize>(op: &T) -> String {
+  serde_json::to_value(op).ok().and_then(|value| value.as_str().map(str::to_string)).unwrap_or_default()
+}
+
+// Infix form used by diagnostics to point at an expression
+impl fmt::Display for Expression {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Expression::UnaryOp { op, arg } => write!(f, "{}{}", symbol(op), arg),
+      Expression::BinaryOp { lhs, op, rhs } => write!(f, "({} {} {})", lhs, symbol(op), rhs),
+      Expression::VariableAccess(name) => write!(f, "{}", name),
+      Expression::FunctionCall { name, args } => {
+        let args: Vec<String> = args.iter().map(Expression::to_string).collect();
+        write!(f, "{}({})", name, args.join(", "))
+      },
+      Expression::TableFunction { name, table } => write!(f, "{}({})", name, table),
+      Expression::TableAccess { table, index } => write!(f, "{}[{}]", table, index),
+      Expression::Conditional { cond, then, otherwise } => write!(f, "({} ? {} : {})", cond, then, otherwise),
+      Expression::NumericLiteral(value) => write!(f, "{}", value)
+    }
+  }
+}
+
+// "export": false keeps a function or global out of the module exports and hides it from importers,
+// "export": "name" exports it with another name
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Export {
+  Public(bool),
+  Renamed(String)
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DeclarationStatement {
   pub function: String,
   pub args: Vec<String>,
-  pub block: Statement
+  pub block: Statement,
+  pub export: Option<Export>
+}
+
+// A global with a constant initial value, "mutable": false makes it read only
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GlobalDeclaration {
+  pub global: String,
+  pub value: Option<Box<Expression>>,
+  pub mutable: Option<bool>,
+  pub export: Option<Export>
+}
+
+// A constant list of numbers, placed in a data segment
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TableDeclaration {
+  pub table: String,
+  pub values: Vec<f64>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImportStatement {
+  pub import: String,
+  #[serde(rename = "as")]
+  pub namespace: Option<String>
+}
+
+// {"strict": true} makes setting a name that isn't a local or a declared global an error in its file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Directive {
+  pub strict: bool
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum TopStatement {
   Statement(Statement),
-  DeclarationStatement(DeclarationStatement)
+  DeclarationStatement(DeclarationStatement),
+  GlobalDeclaration(GlobalDeclaration),
+  TableDeclaration(TableDeclaration),
+  ImportStatement(ImportStatement),
+  Directive(Directive)
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -0,0 +1,7 @@
+use crate::variable_analysis::{ Function, UnaryOp };
+use crate::polyfill_ops::{ Statement, Expression, BinaryOp };
+use crate::polyfill_registry::Library;
+
+// Bodies of the JSONLang library polyfills, generated by build.rs.
+// Calls use the position of the callee in the polyfill dependencies as index, like PolyfillBody::Ir.
+include!(concat!(env!("OUT_DIR"), "/libraries.rs"));
@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+
+use crate::jsonlang::{ Export, Expression };
+use crate::control_simplification::{ SimplifiedStatement, SimplifiedTopStatement };
+use crate::definite_assignment::Scopes;
+use crate::options::{ CompilerOptions, Lint };
+
+// A name declared in a block
+#[derive(Clone)]
+struct Local {
+  used: bool,
+  constant: bool
+}
+
+// Arguments don't have to be read
+const ARGUMENT: Local = Local { used: true, constant: false };
+
+struct Linter<'a> {
+  lints: &'a HashSet<Lint>,
+  // Globals that are declared, by a global declaration or a top level declare
+  globals: HashSet<String>,
+  constants: HashSet<String>,
+  scopes: Scopes<Local>,
+  // Functions called from somewhere else than themselves
+  called: HashSet<String>,
+  // Function being checked, None for the top level code
+  function: Option<String>,
+  warnings: Vec<String>
+}
+
+// Whether the statements after this one in a block never run
+fn exits(stmt: &SimplifiedStatement) -> Option<&'static str> {
+  match stmt {
+    SimplifiedStatement::Return(_) => Some("return"),
+    SimplifiedStatement::Break(_) => Some("break"),
+    SimplifiedStatement::Continue(_) => Some("continue"),
+    SimplifiedStatement::If { then, otherwise, .. } => exits(then).and(exits(otherwise)),
+    SimplifiedStatement::Switch { cases, default, .. } => cases.iter().map(exits).fold(exits(default), |exit, case| case.and(exit)),
+    SimplifiedStatement::Block(block) => block.iter().find_map(exits),
+    _ => None
+  }
+}
+
+impl Linter<'_> {
+  fn report(&mut self, lint: Lint, message: String) {
+    if self.lints.contains(&lint) {
+      self.warnings.push(format!("{} [{}]", message, lint.name()));
+    }
+  }
+  // Reports a warning about the statement being checked
+  fn warn(&mut self, lint: Lint, message: String) {
+    let location = match &self.function {
+      Some(name) => format!("function {}", name),
+      None => "the top level code".to_string()
+    };
+    self.report(lint, format!("{} in {}", message, location));
+  }
+  fn local(&mut self, name: &String) -> Option<&mut Local> {
+    self.scopes.get_mut(name)
+  }
+  fn is_constant(&mut self, expr: &Expression) -> bool {
+    match expr {
+      Expression::UnaryOp { arg, .. } => self.is_constant(arg),
+      Expression::BinaryOp { lhs, rhs, .. } => self.is_constant(lhs) && self.is_constant(rhs),
+      Expression::VariableAccess(name) => match self.local(name) {
+        Some(local) => local.constant,
+        None => self.constants.contains(name)
+      },
+      Expression::Conditional { cond, then, otherwise } => self.is_constant(cond) && self.is_constant(then) && self.is_constant(otherwise),
+      Expression::FunctionCall { .. } | Expression::TableAccess { .. } => false,
+      Expression::TableFunction { .. } | Expression::NumericLiteral(_) => true
+    }
+  }
+  fn call(&mut self, name: &String, args: &[Expression]) {
+    args.iter().for_each(|arg| self.expression(arg));
+    if self.function.as_ref() != Some(name) {
+      self.called.insert(name.clone());
+    }
+  }
+  fn expression(&mut self, expr: &Expression) {
+    match expr {
+      Expression::UnaryOp { arg, .. } => self.expression(arg),
+      Expression::BinaryOp { lhs, rhs, .. } => {
+        self.expression(lhs);
+        self.expression(rhs);
+      },
+      Expression::VariableAccess(name) => if let Some(local) = self.local(name) {
+        local.used = true;
+      },
+      Expression::FunctionCall { name, args } => self.call(name, args),
+      Expression::TableAccess { index, .. } => self.expression(index),
+      Expression::TableFunction { .. } => (),
+      Expression::Conditional { cond, then, otherwise } => {
+        self.expression(cond);
+        if self.is_constant(cond) {
+          self.warn(Lint::ConstantConditions, "condition is always the same".to_string());
+        }
+        self.expression(then);
+        self.expression(otherwise);
+      },
+      Expression::NumericLiteral(_) => ()
+    }
+  }
+  fn declare(&mut self, name: &String, constant: bool) {
+    if self.scopes.declare(name, Local { used: constant, constant }).is_some() {
+      self.warn(Lint::Shadowing, format!("{} is declared again in the same block", name));
+    }
+  }
+  fn statement(&mut self, stmt: &SimplifiedStatement) {
+    match stmt {
+      SimplifiedStatement::Loop { body, step } => {
+        self.statement(body);
+        self.statement(step);
+      },
+      SimplifiedStatement::If { cond, then, otherwise } => {
+        self.expression(cond);
+        if self.is_constant(cond) {
+          self.warn(Lint::ConstantConditions, "condition is always the same".to_string());
+        }
+        self.statement(then);
+        self.statement(otherwise);
+      },
+      SimplifiedStatement::Switch { value, labels, cases, default } => {
+        self.expression(&Expression::VariableAccess(value.clone()));
+        labels.iter().for_each(|label| self.expression(label));
+        cases.iter().chain([&**default]).for_each(|stmt| self.statement(stmt));
+      },
+      SimplifiedStatement::Break(_) | SimplifiedStatement::Continue(_) => (),
+      SimplifiedStatement::Declare(name, value) => {
+        self.expression(value);
+        self.declare(name, false);
+      },
+      SimplifiedStatement::Const(name, value) => {
+        self.expression(value);
+        self.declare(name, true);
+      },
+      SimplifiedStatement::Set(name, value) => {
+        self.expression(value);
+        if self.function.is_some() && self.local(name).is_none() && !self.globals.contains(name) {
+          self.warn(Lint::ImplicitGlobals, format!("set {} implicitly creates a global", name));
+        }
+      },
+      // Reads of a local by its own update don't make it used
+      SimplifiedStatement::Update(name, value) => {
+        let used = self.local(name).map(|local| local.used);
+        self.expression(value);
+        if let (Some(used), Some(local)) = (used, self.local(name)) {
+          local.used = used;
+        }
+      },
+      SimplifiedStatement::Call(name, args) => self.call(name, args),
+      SimplifiedStatement::Return(value) => self.expression(value),
+      SimplifiedStatement::Block(block) => {
+        self.scopes.enter_block();
+        // Every statement after the first exit is unreachable
+        let mut exited = None;
+        for stmt in block {
+          if let Some(exit) = exited {
+            self.warn(Lint::UnreachableCode, format!("unreachable code after {}", exit));
+          }
+          self.statement(stmt);
+          exited = exited.or_else(|| exits(stmt));
+        }
+        let mut unused: Vec<String> = self.scopes.exit_block().into_iter()
+          .filter(|(_, local)| !local.used).map(|(name, _)| name).collect();
+        unused.sort();
+        for name in unused {
+          self.warn(Lint::UnusedLocals, format!("local {} is never read", name));
+        }
+      }
+    }
+  }
+}
+
+// Warnings about programs that compile but probably don't do what was meant.
+// Lints look at the simplified program, which still has the names, once variable analysis accepted it,
+// and resolve them with the same scopes as the definite assignment check.
+pub fn lint_program(program: &[SimplifiedTopStatement], options: &CompilerOptions) -> Vec<String> {
+  let mut linter = Linter {
+    lints: &options.lints, globals: HashSet::new(), constants: HashSet::new(),
+    scopes: Scopes::new(&[], ARGUMENT), called: HashSet::new(), function: None, warnings: Vec::new()
+  };
+  for statement in program {
+    match statement {
+      SimplifiedTopStatement::Statement(SimplifiedStatement::Declare(name, _)) => {
+        linter.globals.insert(name.clone());
+      },
+      SimplifiedTopStatement::Statement(SimplifiedStatement::Const(name, _)) => {
+        linter.constants.insert(name.clone());
+      },
+      SimplifiedTopStatement::Global { name, .. } => {
+        linter.globals.insert(name.clone());
+      },
+      _ => ()
+    }
+  }
+  for statement in program {
+    linter.function = None;
+    linter.scopes = Scopes::new(&[], ARGUMENT);
+    match statement {
+      // Top level declarations are globals
+      SimplifiedTopStatement::Statement(SimplifiedStatement::Declare(_, value)) => linter.expression(value),
+      SimplifiedTopStatement::Statement(SimplifiedStatement::Const(..)) => (),
+      SimplifiedTopStatement::Statement(stmt) => linter.statement(stmt),
+      SimplifiedTopStatement::DeclarationStatement { name, args, content, .. } => {
+        linter.function = Some(name.clone());
+        linter.scopes = Scopes::new(args, ARGUMENT);
+        linter.statement(content);
+      },
+      _ => ()
+    }
+  }
+  // Private functions are the only ones that can't be called from outside
+  for statement in program {
+    if let SimplifiedTopStatement::DeclarationStatement { name, export: Some(Export::Public(false)), .. } = statement {
+      if !linter.called.contains(name) {
+        linter.report(Lint::UnusedFunctions, format!("function {} is never called", name));
+      }
+    }
+  }
+  linter.warnings
+}
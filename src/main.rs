@@ -6,8 +6,18 @@ mod assign_labels;
 mod codegen;
 mod fixup_types;
 mod polyfill_ops;
+mod polyfill_registry;
 mod cordic;
-use jsonlang::*;
+mod math;
+mod stdlib;
+mod libraries;
+mod options;
+mod strength_reduction;
+mod modules;
+mod definite_assignment;
+mod lints;
+#[cfg(test)]
+mod interpreter;
 use control_simplification::top_simplify_control_structures;
 use variable_analysis::top_analyze_variables;
 use collapse_expressions::collapse_expressions;
@@ -15,16 +25,34 @@ use assign_labels::assign_labels;
 use codegen::emit_wasm;
 use fixup_types::fix_types;
 use polyfill_ops::polyfill_ops;
+use options::CompilerOptions;
+use modules::load_program;
+use definite_assignment::check_assignments;
+use lints::lint_program;
 
 use std::fs;
 use std::env;
+use std::path::Path;
 
 fn main() {
-  let args: Vec<String> = env::args().collect();
-  let p: JsonLang = serde_json::from_str(&fs::read_to_string(&args[1]).expect("Couldn't read input")).unwrap();
+  let mut options = CompilerOptions::default();
+  let mut paths = Vec::new();
+  for arg in env::args().skip(1) {
+    if arg.starts_with("--") {
+      options.parse_flag(&arg);
+    } else {
+      paths.push(arg);
+    }
+  }
+  let p = top_simplify_control_structures(load_program(Path::new(&paths[0])));
+  check_assignments(&p);
   //print!("{:#?}", serde_json::to_string(&fix_types(top_analyze_variables(top_simplify_control_structures(p)))).unwrap());
+  let program = top_analyze_variables(p.clone());
+  for warning in lint_program(&p, &options) {
+    eprintln!("warning: {}", warning);
+  }
   let wasm = emit_wasm(assign_labels(collapse_expressions(
-    polyfill_ops(fix_types(top_analyze_variables(top_simplify_control_structures(p))))
-  )));
-  fs::write(&args[2], wasm).expect("Couldn't write to output");
+    polyfill_ops(fix_types(program), &options)
+  )), &options);
+  fs::write(&paths[1], wasm).expect("Couldn't write to output");
 }
@@ -0,0 +1 @@
+pub mod math_polyfill;
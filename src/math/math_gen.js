@@ -0,0 +1,485 @@
+const fs = require("fs");
+
+const op = (binop, argl, argr) => ({ binop, argl, argr });
+const neg = (arg) => ({ unop: "-", arg });
+const call = (name, ...args) => ({ call: name, args });
+const declare = (name, value) => ({ declare: name, value });
+const set = (name, value) => ({ set: name, value });
+const ret = (value) => ({ "return": value });
+const when = (cond, then, otherwise) => otherwise === undefined
+  ? { "if": [{ cond, then }] }
+  : { "if": [{ cond, then }], "else": otherwise };
+const loop = (cond, body) => ({ "while": cond, "do": body });
+// c[0] + x * (c[1] + x * (c[2] + ...))
+const horner = (x, c) => c.slice(0, -1).reduceRight((acc, coef) => op("+", coef, op("*", x, acc)), c[c.length - 1]);
+const isNan = (x) => op("~=", x, x);
+const abs = (x) => call("abs", x);
+const NAN = op("/", 0, 0);
+const INF = op("/", 1, 0);
+
+const TWO_63 = 9223372036854775808;
+const LN2_HI = 6.93147180369123816490e-01;
+const LN2_LO = 1.90821492927058770002e-10;
+const LOG2E = 1.4426950408889634;
+const TWO_OVER_PI = 6.36619772367581382433e-01;
+const PIO2_1 = 1.57079632673412561417e+00;
+const PIO2_2 = 6.07710050630396597660e-11;
+const PIO2_2T = 2.02226624879595063154e-21;
+const PI = 3.141592653589793;
+const PI_LO = 1.2246467991473532e-16;
+const PIO2 = 1.5707963267948966;
+const PIO4 = 0.7853981633974483;
+const T3P8 = 2.41421356237309504880;
+const MOREBITS = 6.123233995736765886130e-17;
+
+// fdlibm __kernel_sin / __kernel_cos coefficients, valid on [-pi/4, pi/4]
+const S = [-1.66666666666666324348e-01, 8.33333333332248946124e-03, -1.98412698298579493134e-04,
+  2.75573137070700676789e-06, -2.50507602534068634195e-08, 1.58969099521155010221e-10];
+const C = [4.16666666666666019037e-02, -1.38888888888741095749e-03, 2.48015872894767294178e-05,
+  -2.75573143513906633035e-07, 2.08757232129817482790e-09, -1.13596475577881948265e-11];
+// fdlibm __ieee754_log coefficients
+const LG = [6.666666666666735130e-01, 3.999999999940941908e-01, 2.857142874366239149e-01,
+  2.222219843214978396e-01, 1.818357216161805012e-01, 1.531383769920937332e-01, 1.479819860511658591e-01];
+// fdlibm __ieee754_exp coefficients
+const P = [1.66666666666666019037e-01, -2.77777777770155933842e-03, 6.61375632143793436117e-05,
+  -1.65339022054652515390e-06, 4.13813679705723846039e-08];
+// Cephes atan rational approximation on [0, 0.66], lowest degree first
+const ATAN_P = [-6.485021904942025371773e1, -1.228866684490136173410e2, -7.500855792314704667340e1,
+  -1.615753718733365076637e1, -8.750608600031904122785e-1];
+const ATAN_Q = [1.945506571482613964425e2, 4.853903996359136964868e2, 4.328810604912902668951e2,
+  1.650270098316988542046e2, 2.485846490142306297962e1, 1];
+
+let program = [];
+
+// x * 2^n for integer n, without intermediate overflow for the ranges used here
+program.push({
+  "function": "ldexp",
+  args: ["x", "n"],
+  block: [
+    loop(op(">", "n", 63), [set("x", op("*", "x", TWO_63)), set("n", op("-", "n", 63))]),
+    loop(op("<", "n", -63), [set("x", op("/", "x", TWO_63)), set("n", op("+", "n", 63))]),
+    declare("p", 1),
+    loop(op(">", "n", 0), [set("p", op("*", "p", 2)), set("n", op("-", "n", 1))]),
+    loop(op("<", "n", 0), [set("p", op("/", "p", 2)), set("n", op("+", "n", 1))]),
+    ret(op("*", "x", "p"))
+  ]
+});
+
+// k such that x * 2^-k lies in [sqrt(1/2), sqrt(2)), for finite positive x
+program.push({
+  "function": "log_exponent",
+  args: ["x"],
+  block: [
+    declare("k", 0),
+    loop(op(">=", "x", TWO_63), [set("x", op("/", "x", TWO_63)), set("k", op("+", "k", 63))]),
+    loop(op("<", "x", 1 / TWO_63), [set("x", op("*", "x", TWO_63)), set("k", op("-", "k", 63))]),
+    loop(op(">=", "x", Math.SQRT2), [set("x", op("/", "x", 2)), set("k", op("+", "k", 1))]),
+    loop(op("<", "x", Math.SQRT1_2), [set("x", op("*", "x", 2)), set("k", op("-", "k", 1))]),
+    ret("k")
+  ]
+});
+
+// ln(m) for m in [sqrt(1/2), sqrt(2))
+program.push({
+  "function": "log_reduced",
+  args: ["m"],
+  block: [
+    declare("f", op("-", "m", 1)),
+    declare("s", op("/", "f", op("+", 2, "f"))),
+    declare("z", op("*", "s", "s")),
+    declare("w", op("*", "z", "z")),
+    declare("t1", op("*", "w", horner("w", [LG[1], LG[3], LG[5]]))),
+    declare("t2", op("*", "z", horner("w", [LG[0], LG[2], LG[4], LG[6]]))),
+    declare("hfsq", op("*", op("*", 0.5, "f"), "f")),
+    ret(op("-", "f", op("-", "hfsq", op("*", "s", op("+", "hfsq", op("+", "t1", "t2"))))))
+  ]
+});
+
+const logSpecialCases = [
+  when(isNan("x"), [ret("x")]),
+  when(op("<", "x", 0), [ret(NAN)]),
+  when(op("==", "x", 0), [ret(neg(INF))]),
+  when(op("==", "x", INF), [ret("x")]),
+  declare("k", call("log_exponent", "x")),
+  declare("f", call("log_reduced", call("ldexp", "x", neg("k"))))
+];
+
+program.push({
+  "function": "log",
+  args: ["x"],
+  block: [
+    ...logSpecialCases,
+    ret(op("+", op("*", "k", LN2_HI), op("+", "f", op("*", "k", LN2_LO))))
+  ]
+});
+
+program.push({
+  "function": "log2",
+  args: ["x"],
+  block: [
+    ...logSpecialCases,
+    ret(op("+", "k", op("*", "f", LOG2E)))
+  ]
+});
+
+program.push({
+  "function": "exp",
+  args: ["x"],
+  block: [
+    when(isNan("x"), [ret("x")]),
+    when(op(">", "x", 7.09782712893383973096e+02), [ret(INF)]),
+    when(op("<", "x", -7.45133219101941108420e+02), [ret(0)]),
+    declare("k", call("floor", op("+", op("*", "x", LOG2E), 0.5))),
+    declare("hi", op("-", "x", op("*", "k", LN2_HI))),
+    declare("lo", op("*", "k", LN2_LO)),
+    declare("r", op("-", "hi", "lo")),
+    declare("z", op("*", "r", "r")),
+    declare("c", op("-", "r", op("*", "z", horner("z", P)))),
+    ret(call("ldexp",
+      op("-", 1, op("-", op("-", "lo", op("/", op("*", "r", "c"), op("-", 2, "c"))), "hi")),
+      "k"))
+  ]
+});
+
+program.push({
+  "function": "kernel_sin",
+  args: ["x"],
+  block: [
+    declare("z", op("*", "x", "x")),
+    ret(op("+", "x", op("*", op("*", "z", "x"), horner("z", S))))
+  ]
+});
+
+program.push({
+  "function": "kernel_cos",
+  args: ["x"],
+  block: [
+    declare("z", op("*", "x", "x")),
+    declare("r", op("*", "z", horner("z", C))),
+    declare("hz", op("*", 0.5, "z")),
+    declare("w", op("-", 1, "hz")),
+    ret(op("+", "w", op("+", op("-", op("-", 1, "w"), "hz"), op("*", "z", "r"))))
+  ]
+});
+
+// Cody-Waite reduction: x = k * pi/2 + r, with q = k mod 4 selecting the quadrant
+const reduceQuadrant = [
+  declare("k", call("floor", op("+", op("*", "x", TWO_OVER_PI), 0.5))),
+  declare("r", op("-", op("-", op("-", "x", op("*", "k", PIO2_1)), op("*", "k", PIO2_2)), op("*", "k", PIO2_2T))),
+  declare("q", op("-", "k", op("*", 4, call("floor", op("/", "k", 4)))))
+];
+const quadrants = (results) => [
+  when(op("==", "q", 0), [ret(results[0])]),
+  when(op("==", "q", 1), [ret(results[1])]),
+  when(op("==", "q", 2), [ret(results[2])]),
+  ret(results[3])
+];
+const ksin = call("kernel_sin", "r");
+const kcos = call("kernel_cos", "r");
+
+program.push({
+  "function": "sin",
+  args: ["x"],
+  block: [...reduceQuadrant, ...quadrants([ksin, kcos, neg(ksin), neg(kcos)])]
+});
+
+program.push({
+  "function": "cos",
+  args: ["x"],
+  block: [...reduceQuadrant, ...quadrants([kcos, neg(ksin), neg(kcos), ksin])]
+});
+
+program.push({
+  "function": "tan",
+  args: ["x"],
+  block: [
+    ...reduceQuadrant,
+    when(op("==", op("-", "q", op("*", 2, call("floor", op("/", "q", 2)))), 0),
+      [ret(op("/", ksin, kcos))],
+      [ret(neg(op("/", kcos, ksin)))])
+  ]
+});
+
+program.push({
+  "function": "atan",
+  args: ["x"],
+  block: [
+    when(isNan("x"), [ret("x")]),
+    declare("sign", 1),
+    when(op("<", "x", 0), [set("sign", -1), set("x", neg("x"))]),
+    declare("y", 0),
+    declare("more", 0),
+    when(op(">", "x", T3P8),
+      [set("y", PIO2), set("more", MOREBITS), set("x", neg(op("/", 1, "x")))],
+      [when(op(">", "x", 0.66),
+        [set("y", PIO4), set("more", 0.5 * MOREBITS), set("x", op("/", op("-", "x", 1), op("+", "x", 1)))])]),
+    declare("z", op("*", "x", "x")),
+    set("z", op("/", op("*", "z", horner("z", ATAN_P)), horner("z", ATAN_Q))),
+    set("z", op("+", op("*", "x", "z"), "x")),
+    ret(op("*", "sign", op("+", "y", op("+", "z", "more"))))
+  ]
+});
+
+program.push({
+  "function": "atan2",
+  args: ["y", "x"],
+  block: [
+    when(op("|", isNan("x"), isNan("y")), [ret(op("+", "x", "y"))]),
+    when(op("&", op("==", abs("x"), INF), op("==", abs("y"), INF)), [
+      when(op(">", "x", 0), [set("x", 1)], [set("x", -1)]),
+      when(op(">", "y", 0), [set("y", 1)], [set("y", -1)])
+    ]),
+    when(op("==", "x", 0), [
+      when(op(">", "y", 0), [ret(PIO2)]),
+      when(op("<", "y", 0), [ret(-PIO2)]),
+      ret(0)
+    ]),
+    declare("z", call("atan", abs(op("/", "y", "x")))),
+    when(op("<", "x", 0), [set("z", op("-", PI, op("-", "z", PI_LO)))]),
+    when(op("<", "y", 0), [ret(neg("z"))]),
+    ret("z")
+  ]
+});
+
+const cosine = op("*", op("-", 1, "x"), op("+", 1, "x"));
+
+program.push({
+  "function": "asin",
+  args: ["x"],
+  block: [ret(call("atan2", "x", call("sqrt", cosine)))]
+});
+
+program.push({
+  "function": "acos",
+  args: ["x"],
+  block: [ret(call("atan2", call("sqrt", cosine), "x"))]
+});
+
+program.push({
+  "function": "hypot",
+  args: ["x", "y"],
+  block: [
+    set("x", abs("x")),
+    set("y", abs("y")),
+    when(op("|", op("==", "x", INF), op("==", "y", INF)), [ret(INF)]),
+    when(op("|", isNan("x"), isNan("y")), [ret(op("+", "x", "y"))]),
+    when(op("<", "x", "y"), [declare("t", "x"), set("x", "y"), set("y", "t")]),
+    when(op("==", "x", 0), [ret(0)]),
+    declare("r", op("/", "y", "x")),
+    ret(op("*", "x", call("sqrt", op("+", 1, op("*", "r", "r")))))
+  ]
+});
+
+// ln(i / 32) for i in [23, 45], split in two doubles (hi + lo)
+const LOG_TABLE_START = 23;
+const LOG_TABLE_HI = [-0.33024168687057687, -0.2876820724517809, -0.24686007793152578, -0.2076393647782445,
+  -0.16989903679539747, -0.13353139262452263, -0.09844007281325252, -0.06453852113757118, -0.0317486983145803, 0.0,
+  0.030771658666753687, 0.06062462181643484, 0.08961215868968714, 0.11778303565638346, 0.1451820098444979,
+  0.17185025692665923, 0.19782574332991987, 0.22314355131420976, 0.24783616390458127, 0.27193371548364176,
+  0.2954642128938359, 0.3184537311185346, 0.3409265869705932];
+const LOG_TABLE_LO = [1.0828321637483858e-17, -2.607160616442564e-17, -1.361743371748368e-17, -1.2053243216686129e-17,
+  4.868008764439071e-19, 3.664457663660085e-18, 4.439009633675136e-18, 6.470486661692933e-18, -3.0382263084680858e-18, 0.0,
+  1.0431732029005968e-18, 2.6424025938726934e-18, -5.4268129336647135e-18, -1.1971685747593677e-18, 8.242418783022475e-18,
+  -6.0224538210113705e-18, 1.2821194372980142e-17, -9.091270597324799e-18, -1.2432209578702523e-17, 7.83319637697442e-19,
+  -2.16461086040599e-17, 2.7114779367326236e-17, 1.7467136443544747e-17];
+
+program.push({ "table": "log_table_hi", values: LOG_TABLE_HI });
+program.push({ "table": "log_table_lo", values: LOG_TABLE_LO });
+const lookup = (table, i) => ({ table, index: op("-", i, LOG_TABLE_START) });
+
+// Error free transformations over named locals, used to carry ln(x) with ~2^-66 relative error
+// hi + lo = a + b exactly
+const twoSum = (hi, lo, a, b) => [
+  declare(hi, op("+", a, b)),
+  declare(hi + "_b", op("-", hi, a)),
+  declare(lo, op("+", op("-", a, op("-", hi, hi + "_b")), op("-", b, hi + "_b")))
+];
+// hi + lo = a + b exactly, when |a| >= |b|
+const fastTwoSum = (hi, lo, a, b) => [
+  declare(hi, op("+", a, b)),
+  declare(lo, op("-", b, op("-", hi, a)))
+];
+// Veltkamp split of a into two 26 bit halves
+const split = (a) => [
+  declare(a + "_t", op("*", 134217729, a)),
+  declare(a + "_h", op("-", a + "_t", op("-", a + "_t", a))),
+  declare(a + "_l", op("-", a, a + "_h"))
+];
+// p + e = a * b exactly (Dekker)
+const twoProd = (p, e, a, b) => [
+  ...split(a),
+  ...(a === b ? [] : split(b)),
+  declare(p, op("*", a, b)),
+  declare(e, op("+", op("+", op("+", op("-", op("*", a + "_h", b + "_h"), p), op("*", a + "_h", b + "_l")),
+    op("*", a + "_l", b + "_h")), op("*", a + "_l", b + "_l")))
+];
+
+// x^y for finite x > 0 and finite y, within 1 ulp
+program.push({
+  "function": "pow_magnitude",
+  args: ["x", "y"],
+  block: [
+    // x = 2^k * m, m = c + f with c = i / 32 close to m, ln(m) = ln(c) + 2 atanh(s), s = f / (m + c)
+    declare("k", call("log_exponent", "x")),
+    declare("m", call("ldexp", "x", neg("k"))),
+    declare("i", call("floor", op("+", op("*", "m", 32), 0.5))),
+    declare("c", op("/", "i", 32)),
+    declare("f", op("-", "m", "c")),
+    ...twoSum("d", "d_lo", "m", "c"),
+    declare("s", op("/", "f", "d")),
+    ...twoProd("sd", "sd_lo", "s", "d"),
+    declare("s_lo", op("/", op("-", op("-", op("-", "f", "sd"), "sd_lo"), op("*", "s", "d_lo")), "d")),
+    declare("z", op("*", "s", "s")),
+    declare("corr", op("*", op("*", op("*", 2, "s"), "z"), horner("z", [1 / 3, 1 / 5, 1 / 7, 1 / 9]))),
+    // ln(x) = k ln(2) + ln(c) + 2s + small terms, as lx + lx_lo
+    declare("kln2", op("*", "k", LN2_HI)),
+    declare("lnc", lookup("log_table_hi", "i")),
+    ...twoSum("a", "a_lo", "kln2", "lnc"),
+    declare("s2", op("*", 2, "s")),
+    ...twoSum("b", "b_lo", "a", "s2"),
+    declare("lo", op("+", op("+", op("+", "a_lo", "b_lo"), op("+", op("*", 2, "s_lo"), "corr")),
+      op("+", op("*", "k", LN2_LO), lookup("log_table_lo", "i")))),
+    ...fastTwoSum("lx", "lx_lo", "b", "lo"),
+    // y ln(x) as p + p_lo
+    ...twoProd("p", "p_e", "y", "lx"),
+    when(op(">", "p", 7.09782712893383973096e+02), [ret(INF)]),
+    when(op("<", "p", -7.45133219101941108420e+02), [ret(0)]),
+    declare("p_lo", op("+", "p_e", op("*", "y", "lx_lo"))),
+    // exp(p + p_lo), the low part is folded into the exp reduction
+    declare("n", call("floor", op("+", op("*", "p", LOG2E), 0.5))),
+    declare("hi", op("-", "p", op("*", "n", LN2_HI))),
+    declare("elo", op("-", op("*", "n", LN2_LO), "p_lo")),
+    declare("r", op("-", "hi", "elo")),
+    declare("rz", op("*", "r", "r")),
+    declare("rc", op("-", "r", op("*", "rz", horner("rz", P)))),
+    ret(call("ldexp",
+      op("-", 1, op("-", op("-", "elo", op("/", op("*", "r", "rc"), op("-", 2, "rc"))), "hi")),
+      "n"))
+  ]
+});
+
+// a * b in double-double, hi + lo = (a + a_lo) * (b + b_lo) with ~2^-104 relative error
+const ddMul = (hi, lo, a, a_lo, b, b_lo) => [
+  ...twoProd(hi + "_p", hi + "_e", a, b),
+  set(hi + "_e", op("+", hi + "_e", op("+", op("*", a, b_lo), op("*", a_lo, b)))),
+  ...fastTwoSum(hi + "_s", lo + "_s", hi + "_p", hi + "_e"),
+  set(hi, hi + "_s"),
+  set(lo, lo + "_s")
+];
+const outOfRange = (x) => op("|", op("<", x, 2 ** -969), op(">", x, 2 ** 996));
+
+// x^n for finite x > 0 and integer n, by squaring in double-double so representable results are exact.
+// Returns NaN when a value leaves [2^-969, 2^996], where the products would lose precision.
+program.push({
+  "function": "pow_integer",
+  args: ["x", "n"],
+  block: [
+    declare("inverse", 0),
+    when(op("<", "n", 0), [set("n", neg("n")), set("inverse", 1)]),
+    declare("b", "x"),
+    declare("b_lo", 0),
+    declare("r", 1),
+    declare("r_lo", 0),
+    loop(op(">", "n", 0), [
+      declare("half", call("floor", op("/", "n", 2))),
+      when(op("~=", op("*", "half", 2), "n"), [
+        ...ddMul("r", "r_lo", "r", "r_lo", "b", "b_lo"),
+        when(outOfRange("r"), [ret(NAN)])
+      ]),
+      set("n", "half"),
+      when(op(">", "n", 0), [
+        ...ddMul("b", "b_lo", "b", "b_lo", "b", "b_lo"),
+        when(outOfRange("b"), [ret(NAN)])
+      ])
+    ]),
+    when(op("==", "inverse", 0), [ret("r")]),
+    // 1 / (r + r_lo) with one Newton step
+    declare("q", op("/", 1, "r")),
+    ...twoProd("qr", "qr_e", "q", "r"),
+    ret(op("+", "q", op("*", "q", op("-", op("-", op("-", 1, "qr"), "qr_e"), op("*", "q", "r_lo")))))
+  ]
+});
+
+// IEEE 754 pow special cases, then exact integer powers, then the sign of the result and |x|^y.
+// `reduce` runs right before computing the magnitude, when x > 0 and y isn't zero or an exact integer power
+const powSign = (magnitude, reduce = []) => [
+  when(op("==", "y", 0), [ret(1)]),
+  when(op("==", "x", 1), [ret(1)]),
+  when(op("|", isNan("x"), isNan("y")), [ret(op("+", "x", "y"))]),
+  declare("integer", 0),
+  declare("odd", 0),
+  when(op("==", call("floor", "y"), "y"), [
+    set("integer", 1),
+    when(op("~=", op("*", 2, call("floor", op("/", "y", 2))), "y"), [set("odd", 1)])
+  ]),
+  when(op("==", "x", 0), [
+    when(op("<", "y", 0), [
+      when(op("==", "odd", 1), [ret(call("copysign", INF, "x"))]),
+      ret(INF)
+    ]),
+    when(op("==", "odd", 1), [ret("x")]),
+    ret(0)
+  ]),
+  when(op("==", abs("y"), INF), [
+    when(op("==", "x", -1), [ret(1)]),
+    when(op("<", abs("x"), 1), [
+      when(op(">", "y", 0), [ret(0)]),
+      ret(INF)
+    ]),
+    when(op(">", "y", 0), [ret(INF)]),
+    ret(0)
+  ]),
+  when(op("==", abs("x"), INF), [
+    when(op("<", "y", 0), [
+      when(op("==", "odd", 1), [ret(call("copysign", 0, "x"))]),
+      ret(0)
+    ]),
+    when(op("==", "odd", 1), [ret("x")]),
+    ret(INF)
+  ]),
+  declare("sign", 1),
+  when(op("<", "x", 0), [
+    when(op("==", "integer", 0), [ret(NAN)]),
+    when(op("==", "odd", 1), [set("sign", -1)]),
+    set("x", neg("x"))
+  ]),
+  when(op("==", "integer", 1), [
+    declare("exact", call("pow_integer", "x", "y")),
+    when(op("==", "exact", "exact"), [ret(op("*", "sign", "exact"))])
+  ]),
+  ...reduce,
+  ret(op("*", "sign", magnitude))
+];
+
+// Accurate x^y, within 1 ulp
+program.push({
+  "function": "pow",
+  args: ["x", "y"],
+  block: powSign(call("pow_magnitude", "x", "y"))
+});
+
+// Fast x^y, the error grows with |y ln(x)|, up to ~|y ln(x)| ulp
+program.push({
+  "function": "pow_fast",
+  args: ["x", "y"],
+  block: powSign(call("exp", op("*", "y", call("log", "x"))))
+});
+
+// CORDIC exp and ln from the cordic library, only for the fractional part of y as the original cordic pow did.
+// exp_cordic loops once per unit of its argument, so results that overflow or underflow are caught before
+program.push({
+  "function": "pow_cordic",
+  args: ["x", "y"],
+  block: powSign(call("#exp_cordic", op("*", "y", call("#ln_cordic", "x"))), [
+    declare("l", op("*", "y", call("log", "x"))),
+    when(op(">", "l", 7.09782712893383973096e+02), [ret(op("*", "sign", INF))]),
+    when(op("<", "l", -7.45133219101941108420e+02), [ret(op("*", "sign", 0))]),
+    declare("whole", call("pow_integer", "x", call("floor", "y"))),
+    when(op("==", "whole", "whole"), [
+      ret(op("*", op("*", "sign", "whole"),
+        call("#exp_cordic", op("*", op("-", "y", call("floor", "y")), call("#ln_cordic", "x")))))
+    ])
+  ])
+});
+
+fs.writeFileSync("math.lang.json", JSON.stringify(program, null, 1));
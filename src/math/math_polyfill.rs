@@ -0,0 +1,224 @@
+use crate::polyfill_registry::{ Library, Polyfill, PolyfillBody };
+
+// Math functions callable from JSONLang, implemented in math.lang.json (generated by math_gen.js).
+// Maximum error against Rust's f64 functions, checked by the math_errors test:
+//  exp, log, log2, atan, atan2: 1 ulp
+//  sin, cos, asin, acos, hypot: 2 ulp
+//  tan: 3 ulp
+// The accurate pow (#pow) is within 1 ulp of f64::powf, #pow_fast loses precision as |y * log(x)| grows.
+// sin, cos and tan use a three part Cody-Waite reduction, which is only accurate for |x| <= 2^20.
+// atan2 doesn't distinguish signed zeros, atan2(0, -1) is always pi.
+pub const MATH_BUILTINS: [&str; 11] = [
+  "sin", "cos", "tan", "asin", "acos", "atan", "atan2", "log", "log2", "exp", "hypot"
+];
+
+pub const MATH_LIBRARY: Library = Library { name: "math", source: include_str!("math.lang.json") };
+
+const fn math(name: &'static str, arguments: u32, dependencies: &'static [&'static str]) -> Polyfill {
+  Polyfill { name, arguments, dependencies, body: PolyfillBody::JsonLang(&MATH_LIBRARY) }
+}
+
+pub const MATH_POLYFILLS: &[Polyfill] = &[
+  math("#ldexp", 2, &[]),
+  math("#log_exponent", 1, &[]),
+  math("#log_reduced", 1, &[]),
+  math("#log", 1, &["#log_exponent", "#log_reduced", "#ldexp"]),
+  math("#log2", 1, &["#log_exponent", "#log_reduced", "#ldexp"]),
+  math("#exp", 1, &["#ldexp"]),
+  math("#kernel_sin", 1, &[]),
+  math("#kernel_cos", 1, &[]),
+  math("#sin", 1, &["#kernel_sin", "#kernel_cos"]),
+  math("#cos", 1, &["#kernel_sin", "#kernel_cos"]),
+  math("#tan", 1, &["#kernel_sin", "#kernel_cos"]),
+  math("#atan", 1, &[]),
+  math("#atan2", 2, &["#atan"]),
+  math("#asin", 1, &["#atan2"]),
+  math("#acos", 1, &["#atan2"]),
+  math("#hypot", 2, &[]),
+  math("#pow_magnitude", 2, &["#log_exponent", "#ldexp", "#log_table_hi", "#log_table_lo"]),
+  math("#pow_integer", 2, &[]),
+  math("#pow", 2, &["#pow_integer", "#pow_magnitude"]),
+  math("#pow_fast", 2, &["#pow_integer", "#exp", "#log"]),
+  math("#pow_cordic", 2, &["#pow_integer", "#log", "#ln_cordic", "#exp_cordic"])
+];
+
+// These tests run the IR in the interpreter, so they check the semantics of the polyfills and not the emitted wasm,
+// the pow.edge_cases integration test runs POW_EDGE_CASES through the compiled module.
+#[cfg(test)]
+mod tests {
+  use crate::interpreter::Interpreter;
+  use crate::polyfill_registry::find_polyfill;
+  use crate::options::{ CompilerOptions, PowImplementation };
+
+  const POW_PROGRAM: &str = r#"[{"function": "f", "args": ["x", "y"], "block": [{"return": {"binop": "^", "argl": "x", "argr": "y"}}]}]"#;
+
+  fn ordered_bits(x: f64) -> i64 {
+    let bits = x.to_bits() as i64;
+    if bits < 0 { -(bits & i64::MAX) } else { bits }
+  }
+
+  fn ulp_error(result: f64, expected: f64) -> u64 {
+    if result == expected || (result.is_nan() && expected.is_nan()) {
+      return 0
+    }
+    ordered_bits(result).abs_diff(ordered_bits(expected))
+  }
+
+  // Uniform numbers in [0, 1) from a fixed seed, so every run sweeps the same values
+  fn random_numbers() -> impl FnMut() -> f64 {
+    let mut seed: u64 = 0x2545f4914f6cdd1d;
+    move || {
+      seed ^= seed << 13;
+      seed ^= seed >> 7;
+      seed ^= seed << 17;
+      (seed >> 11) as f64 / (1u64 << 53) as f64
+    }
+  }
+
+  // Largest ulp error of x^y against f64::powf over (x range, y range) sweeps with finite normal results
+  fn max_pow_error(pow: PowImplementation) -> u64 {
+    let program = Interpreter::compile(POW_PROGRAM, &CompilerOptions { pow, ..CompilerOptions::default() });
+    let ranges = [(0.0, 10.0, -50.0, 50.0), (0.9, 1.1, -5000.0, 5000.0), (0.0, 1e10, -30.0, 30.0),
+      (0.0, 1e-300, -1.0, 1.0), (0.999999, 1.000001, -1e8, 1e8), (-10.0, 0.0, -40.0, 40.0)];
+    let mut random = random_numbers();
+    let mut max = 0;
+    for (x_low, x_high, y_low, y_high) in ranges {
+      for i in 0..2000 {
+        let x = x_low + (x_high - x_low) * random();
+        let mut y = y_low + (y_high - y_low) * random();
+        if x < 0.0 || i % 2 == 0 {
+          y = y.round();
+        }
+        let expected = x.powf(y);
+        if !expected.is_normal() {
+          continue
+        }
+        max = max.max(ulp_error(program.call("f", &[x, y]), expected));
+      }
+    }
+    max
+  }
+
+  // IEEE 754 special cases and integer powers, which every implementation must match exactly.
+  // Keep in sync with pow.edge_cases in test/compiler_integration.test.js
+  const POW_EDGE_CASES: &[(f64, f64)] = &[
+    (0.0, 0.0), (-0.0, -0.0), (f64::NAN, 0.0), (f64::INFINITY, -0.0), (1.0, f64::NAN), (1.0, f64::INFINITY),
+    (f64::NAN, 1.0), (2.0, f64::NAN), (f64::NAN, f64::NAN), (-1.0, f64::NAN),
+    (0.0, 3.0), (-0.0, 3.0), (-0.0, 2.0), (-0.0, 0.5), (0.0, -3.0), (-0.0, -3.0), (-0.0, -2.0), (-0.0, -0.5),
+    (0.0, f64::INFINITY), (-0.0, f64::NEG_INFINITY),
+    (-1.0, f64::INFINITY), (-1.0, f64::NEG_INFINITY), (0.5, f64::INFINITY), (-0.5, f64::NEG_INFINITY),
+    (2.0, f64::INFINITY), (-2.0, f64::NEG_INFINITY), (-2.0, f64::INFINITY),
+    (f64::INFINITY, 2.0), (f64::INFINITY, 0.5), (f64::INFINITY, -3.0),
+    (f64::NEG_INFINITY, 3.0), (f64::NEG_INFINITY, 2.0), (f64::NEG_INFINITY, 0.5),
+    (f64::NEG_INFINITY, -3.0), (f64::NEG_INFINITY, -2.0), (f64::NEG_INFINITY, -0.5),
+    (-2.0, 0.5), (-8.0, 1.0 / 3.0), (-2.0, 1e300), (-2.0, -1e300),
+    (2.0, 9.0), (3.0, 2.0), (-2.0, 3.0), (-2.0, -3.0), (-3.0, 4.0), (3.0, 20.0), (10.0, 22.0), (10.0, -2.0),
+    (0.5, -10.0), (7.0, -3.0), (1.5, 5.0), (-1.1, 7.0), (0.1, 3.0), (1e-10, 20.0), (1.0 + f64::EPSILON, 1e15)
+  ];
+
+  #[test]
+  fn pow_edge_cases() {
+    for pow in [PowImplementation::Accurate, PowImplementation::Fast, PowImplementation::Cordic] {
+      let program = Interpreter::compile(POW_PROGRAM, &CompilerOptions { pow, ..CompilerOptions::default() });
+      for &(x, y) in POW_EDGE_CASES {
+        let result = program.call("f", &[x, y]);
+        let expected = x.powf(y);
+        assert!(result.to_bits() == expected.to_bits() || (result.is_nan() && expected.is_nan()),
+          "{:?}: {}^{} is {}, expected {}", pow, x, y, result, expected);
+      }
+    }
+  }
+
+  // Constant exponents that strength reduction replaces, with results that are subnormal or overflow
+  const REDUCED_POW_CASES: &[(f64, f64)] = &[
+    (1e-160, 2.0), (1e200, 2.0), (-1e200, 2.0), (3e-308, -1.0), (1e-320, -1.0), (-0.0, -1.0), (f64::NEG_INFINITY, -1.0),
+    (f64::NAN, 0.5), (-1.0, 0.5), (2.0, 0.5), (1e-320, 0.5)
+  ];
+  // Constant exponents that would lose precision as a multiplication chain, they are left to #pow
+  const CHAIN_POW_CASES: &[(f64, f64)] = &[(1e-77, -4.0), (1e-100, -3.0), (1e-160, -2.0), (1.1, 4.0), (1e77, 4.0)];
+
+  fn constant_pow_program(y: f64) -> Interpreter {
+    let source = POW_PROGRAM.replace(r#""argr": "y""#, &format!(r#""argr": {:?}"#, y));
+    Interpreter::compile(&source, &CompilerOptions::default())
+  }
+
+  fn constant_pow(x: f64, y: f64) -> f64 {
+    constant_pow_program(y).call("f", &[x, y])
+  }
+
+  #[test]
+  fn constant_pow_edge_cases() {
+    for &(x, y) in POW_EDGE_CASES.iter().chain(REDUCED_POW_CASES).filter(|(_, y)| y.is_finite()) {
+      let result = constant_pow(x, y);
+      let expected = x.powf(y);
+      assert!(result.to_bits() == expected.to_bits() || (result.is_nan() && expected.is_nan()),
+        "{}^{} is {}, expected {}", x, y, result, expected);
+    }
+    for &(x, y) in CHAIN_POW_CASES {
+      assert!(ulp_error(constant_pow(x, y), x.powf(y)) <= 1, "{}^{} is {}, expected {}", x, y, constant_pow(x, y), x.powf(y));
+    }
+  }
+
+  // x ^ 3 is x * x * x, which must stay within the 1 ulp of #pow, also where x * x is subnormal or overflows
+  #[test]
+  fn cube_errors() {
+    let program = constant_pow_program(3.0);
+    let mut random = random_numbers();
+    for _ in 0..20000 {
+      let x = (1.0 + random()) * 2f64.powi((random() * 2100.0) as i32 - 1050) * if random() < 0.5 { -1.0 } else { 1.0 };
+      let (result, expected) = (program.call("f", &[x, 3.0]), x.powf(3.0));
+      assert!(ulp_error(result, expected) <= 1, "{}^3 is {}, expected {}", x, result, expected);
+    }
+  }
+
+  // Checks that the largest ulp error of a math builtin against the Rust implementation is within the bound,
+  // with every argument taken from each of the ranges, over results that are finite and normal
+  fn check_errors(name: &str, expected: fn(&[f64]) -> f64, ranges: &[(f64, f64)], bound: u64) {
+    let arguments = find_polyfill(&format!("#{}", name)).unwrap().arguments as usize;
+    let args: Vec<String> = (0..arguments).map(|i| format!("\"a{}\"", i)).collect();
+    let source = format!(r#"[{{"function": "f", "args": [{0}], "block": [{{"return": {{"call": "{1}", "args": [{0}]}}}}]}}]"#,
+      args.join(", "), name);
+    let program = Interpreter::compile(&source, &CompilerOptions::default());
+    let mut random = random_numbers();
+    let mut max = 0;
+    for &(low, high) in ranges {
+      for _ in 0..2000 {
+        let args: Vec<f64> = (0..arguments).map(|_| low + (high - low) * random()).collect();
+        let expected = expected(&args);
+        if expected.is_normal() {
+          max = max.max(ulp_error(program.call("f", &args), expected));
+        }
+      }
+    }
+    assert!(max <= bound, "{} is off by {} ulp, more than {}", name, max, bound);
+  }
+
+  // The bounds documented at the top of the file
+  #[test]
+  fn math_errors() {
+    let trigonometric = [(-1.0, 1.0), (-10.0, 10.0), (-1e3, 1e3), (-1048576.0, 1048576.0)];
+    let positive = [(0.0, 1e-300), (0.0, 1.0), (0.5, 2.0), (0.0, 1e10), (0.0, 1e300)];
+    let inverse = [(-1.0, 1.0), (-0.1, 0.1), (0.9, 1.0)];
+    check_errors("exp", |a| a[0].exp(), &[(-1.0, 1.0), (-700.0, 700.0), (-745.0, -700.0)], 1);
+    check_errors("log", |a| a[0].ln(), &positive, 1);
+    check_errors("log2", |a| a[0].log2(), &positive, 1);
+    check_errors("atan", |a| a[0].atan(), &[(-1.0, 1.0), (-10.0, 10.0), (-1e10, 1e10)], 1);
+    check_errors("atan2", |a| a[0].atan2(a[1]), &[(-1.0, 1.0), (-1e5, 1e5)], 1);
+    check_errors("sin", |a| a[0].sin(), &trigonometric, 2);
+    check_errors("cos", |a| a[0].cos(), &trigonometric, 2);
+    check_errors("asin", |a| a[0].asin(), &inverse, 2);
+    check_errors("acos", |a| a[0].acos(), &inverse, 2);
+    check_errors("hypot", |a| a[0].hypot(a[1]), &[(-1.0, 1.0), (-1e150, 1e150), (0.0, 1e-300)], 2);
+    check_errors("tan", |a| a[0].tan(), &trigonometric, 3);
+  }
+
+  #[test]
+  fn accurate_pow_error() {
+    assert!(max_pow_error(PowImplementation::Accurate) <= 1);
+  }
+
+  #[test]
+  fn fast_pow_error() {
+    assert!(max_pow_error(PowImplementation::Fast) <= 1024);
+  }
+}
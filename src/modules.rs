@@ -0,0 +1,309 @@
+use std::collections::{ HashMap, HashSet };
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+use crate::jsonlang::*;
+
+// Loads a JSONLang file and every file it imports into a single program.
+// {"import": "path", "as": "name"} loads path, relative to the importing file, and makes its functions,
+// globals and tables available as name.function, name.global and name.table. The name defaults to the file stem.
+// Each file is loaded once, its top level statements run before the ones of the first file importing it.
+// Functions and globals declared with "export": false can't be used from other files.
+// In files with {"strict": true} a set must be to a local or to a global declared by some loaded file.
+pub fn load_program(path: &Path) -> JsonLang {
+  let mut loader = Loader {
+    loaded: HashMap::new(), loading: Vec::new(), private: HashSet::new(), declared: HashSet::new(), functions: Vec::new(), main: Vec::new()
+  };
+  loader.load(path, String::new());
+  let mut statements = loader.functions;
+  statements.append(&mut loader.main);
+  JsonLang { statements }
+}
+
+struct Loader {
+  // Prefix given to the names of every loaded file
+  loaded: HashMap<PathBuf, String>,
+  // Files being loaded, to report import cycles
+  loading: Vec<PathBuf>,
+  // Renamed functions and globals that are private to their file
+  private: HashSet<String>,
+  // Renamed globals declared with a global declaration or a top level declare
+  declared: HashSet<String>,
+  functions: Vec<TopStatement>,
+  main: Vec<TopStatement>
+}
+
+impl Loader {
+  fn load(&mut self, path: &Path, prefix: String) -> String {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| panic!("Couldn't read {}", path.display()));
+    if let Some(prefix) = self.loaded.get(&path) {
+      return prefix.clone()
+    }
+    if let Some(start) = self.loading.iter().position(|loading| *loading == path) {
+      let cycle: Vec<String> = self.loading[start..].iter().chain([&path]).map(|p| p.display().to_string()).collect();
+      panic!("Import cycle: {}", cycle.join(" -> "))
+    }
+    self.loading.push(path.clone());
+    let source = fs::read_to_string(&path).unwrap_or_else(|_| panic!("Couldn't read {}", path.display()));
+    let program: JsonLang = serde_json::from_str(&source)
+      .unwrap_or_else(|err| panic!("Couldn't parse {}: {}", path.display(), err));
+
+    let mut renamer = Renamer {
+      prefix: prefix.clone(), imports: HashMap::new(), functions: HashSet::new(), tables: HashSet::new(),
+      private: HashSet::new(), declared: HashSet::new(), strict: false, locals: Vec::new()
+    };
+    let mut statements = Vec::new();
+    for statement in program.statements {
+      match statement {
+        TopStatement::ImportStatement(import) => {
+          let import_path = path.parent().unwrap().join(&import.import);
+          let namespace = import.namespace.unwrap_or_else(|| {
+            import_path.file_stem().unwrap().to_string_lossy().to_string()
+          });
+          if renamer.imports.contains_key(&namespace) {
+            panic!("Duplicate import namespace {} in {}", namespace, path.display())
+          }
+          let import_prefix = self.load(&import_path, format!("{}{}.", prefix, namespace));
+          renamer.imports.insert(namespace, import_prefix);
+        },
+        TopStatement::DeclarationStatement(decl) => {
+          renamer.functions.insert(decl.function.clone());
+          statements.push(TopStatement::DeclarationStatement(decl));
+        },
+        TopStatement::TableDeclaration(table) => {
+          renamer.tables.insert(table.table.clone());
+          statements.push(TopStatement::TableDeclaration(table));
+        },
+        TopStatement::GlobalDeclaration(ref global) => {
+          self.declared.insert(format!("{}{}", prefix, global.global));
+          statements.push(statement);
+        },
+        TopStatement::Statement(Statement::Declare { ref declare, .. }) => {
+          self.declared.insert(format!("{}{}", prefix, declare));
+          statements.push(statement);
+        },
+        TopStatement::Directive(directive) => renamer.strict = directive.strict,
+        statement => statements.push(statement)
+      }
+    }
+
+    renamer.private = self.private.clone();
+    renamer.declared = self.declared.clone();
+    let mut main = Vec::new();
+    for statement in statements {
+      match statement {
+        TopStatement::DeclarationStatement(decl) => {
+          renamer.locals = vec![decl.args.iter().cloned().collect()];
+          let function = renamer.function(decl.function);
+          if matches!(decl.export, Some(Export::Public(false))) {
+            self.private.insert(function.clone());
+          }
+          self.functions.push(TopStatement::DeclarationStatement(DeclarationStatement {
+            function,
+            args: decl.args,
+            block: renamer.statement(decl.block),
+            export: decl.export
+          }));
+        },
+        TopStatement::GlobalDeclaration(global) => {
+          let name = format!("{}{}", prefix, global.global);
+          if matches!(global.export, Some(Export::Public(false))) {
+            self.private.insert(name.clone());
+          }
+          renamer.locals = vec![HashSet::new()];
+          self.functions.push(TopStatement::GlobalDeclaration(GlobalDeclaration {
+            global: name,
+            value: global.value.map(|value| renamer.boxed_expression(*value)),
+            mutable: global.mutable,
+            export: global.export
+          }));
+        },
+        TopStatement::TableDeclaration(table) => {
+          self.functions.push(TopStatement::TableDeclaration(TableDeclaration {
+            table: renamer.table(table.table),
+            values: table.values
+          }));
+        },
+        // Top level declarations and constants belong to the file
+        TopStatement::Statement(Statement::Declare { declare, value }) => {
+          renamer.locals = vec![HashSet::new()];
+          main.push(Statement::Declare { declare: format!("{}{}", prefix, declare), value: renamer.boxed_expression(*value) });
+        },
+        // Constants stay in order with globals, whose initial values can use them
+        TopStatement::Statement(Statement::Const { name, value }) => {
+          renamer.locals = vec![HashSet::new()];
+          self.functions.push(TopStatement::Statement(Statement::Const {
+            name: format!("{}{}", prefix, name), value: renamer.boxed_expression(*value)
+          }));
+        },
+        TopStatement::Statement(stmt) => {
+          renamer.locals = vec![HashSet::new()];
+          main.push(renamer.statement(stmt));
+        },
+        TopStatement::ImportStatement(_) | TopStatement::Directive(_) => unreachable!()
+      }
+    }
+    self.main.extend(main.into_iter().map(TopStatement::Statement));
+    self.loading.pop();
+    self.loaded.insert(path, prefix.clone());
+    prefix
+  }
+}
+
+// Renames the functions and globals of a file to their names in the merged program
+struct Renamer {
+  prefix: String,
+  // Prefix of every imported file, by namespace
+  imports: HashMap<String, String>,
+  functions: HashSet<String>,
+  tables: HashSet<String>,
+  // Private functions and globals of the imported files
+  private: HashSet<String>,
+  // Globals that can be set in strict files
+  declared: HashSet<String>,
+  strict: bool,
+  locals: Vec<HashSet<String>>
+}
+
+impl Renamer {
+  fn imported(&self, name: &str) -> Option<String> {
+    let (namespace, name) = name.split_once('.')?;
+    let imported = self.imports.get(namespace).map(|prefix| format!("{}{}", prefix, name))?;
+    if self.private.contains(&imported) {
+      panic!("{} is private to {}", name, namespace)
+    }
+    Some(imported)
+  }
+  fn function(&self, name: String) -> String {
+    if let Some(name) = self.imported(&name) {
+      name
+    } else if self.functions.contains(&name) {
+      format!("{}{}", self.prefix, name)
+    } else {
+      // Builtins and undefined functions, which are reported later
+      name
+    }
+  }
+  fn table(&self, name: String) -> String {
+    if let Some(name) = self.imported(&name) {
+      name
+    } else if self.tables.contains(&name) {
+      format!("{}{}", self.prefix, name)
+    } else {
+      name
+    }
+  }
+  fn variable(&self, name: String) -> String {
+    if self.locals.iter().any(|scope| scope.contains(&name)) {
+      name
+    } else if let Some(name) = self.imported(&name) {
+      name
+    } else {
+      format!("{}{}", self.prefix, name)
+    }
+  }
+  fn declare(&mut self, name: &str) {
+    self.locals.last_mut().unwrap().insert(name.to_string());
+  }
+  fn block<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+    self.locals.push(HashSet::new());
+    let result = f(self);
+    self.locals.pop();
+    result
+  }
+  fn boxed_statement(&mut self, stmt: Statement) -> Box<Statement> {
+    Box::new(self.statement(stmt))
+  }
+  fn boxed_expression(&mut self, expr: Expression) -> Box<Expression> {
+    Box::new(self.expression(expr))
+  }
+  fn statement(&mut self, stmt: Statement) -> Statement {
+    match stmt {
+      Statement::If { branches, else_branch } => Statement::If {
+        branches: branches.into_iter().map(|branch| IfConditions {
+          cond: self.expression(branch.cond),
+          then: self.boxed_statement(*branch.then)
+        }).collect(),
+        else_branch: else_branch.map(|stmt| self.boxed_statement(*stmt))
+      },
+      Statement::While { condition, do_block, label } => Statement::While {
+        condition: self.boxed_expression(*condition),
+        do_block: self.boxed_statement(*do_block),
+        label
+      },
+      Statement::StatementList(list) => Statement::StatementList(self.block(|s| {
+        list.into_iter().map(|stmt| s.statement(stmt)).collect()
+      })),
+      Statement::Iterator { iterator, from, to, step, do_block, label } => self.block(|s| {
+        s.declare(&iterator);
+        Statement::Iterator {
+          from: s.boxed_expression(*from),
+          to: s.boxed_expression(*to),
+          step: step.map(|step| s.boxed_expression(*step)),
+          do_block: s.boxed_statement(*do_block),
+          iterator,
+          label
+        }
+      }),
+      Statement::Until { do_block, until, label } => Statement::Until {
+        do_block: self.boxed_statement(*do_block),
+        until: self.boxed_expression(*until),
+        label
+      },
+      Statement::Declare { declare, value } => {
+        self.declare(&declare);
+        Statement::Declare { value: self.boxed_expression(*value), declare }
+      },
+      Statement::Const { name, value } => {
+        self.declare(&name);
+        Statement::Const { value: self.boxed_expression(*value), name }
+      },
+      Statement::Set { set, value } => {
+        let name = self.variable(set.clone());
+        if self.strict && !self.locals.iter().any(|scope| scope.contains(&set)) && !self.declared.contains(&name) {
+          panic!("{} isn't declared, globals must be declared in strict files", set)
+        }
+        Statement::Set { set: name, value: self.boxed_expression(*value) }
+      },
+      // Updates don't create globals, so they are allowed in strict files
+      Statement::Update { name, op, value } => Statement::Update { name: self.variable(name), op, value: self.boxed_expression(*value) },
+      Statement::Increment { increment } => Statement::Increment { increment: self.variable(increment) },
+      Statement::Decrement { decrement } => Statement::Decrement { decrement: self.variable(decrement) },
+      Statement::Call { name, args } => Statement::Call {
+        name: self.function(name),
+        args: args.into_iter().map(|arg| self.expression(arg)).collect()
+      },
+      Statement::Return { return_value } => Statement::Return { return_value: self.boxed_expression(*return_value) },
+      Statement::Switch { value, cases, default } => Statement::Switch {
+        value: self.boxed_expression(*value),
+        cases: cases.into_iter().map(|case| SwitchCase { case: self.expression(case.case), then: self.boxed_statement(*case.then) }).collect(),
+        default: default.map(|stmt| self.boxed_statement(*stmt))
+      },
+      Statement::Break { label } => Statement::Break { label },
+      Statement::Continue { label } => Statement::Continue { label },
+      Statement::Other(s) => Statement::Other(s)
+    }
+  }
+  fn expression(&mut self, expr: Expression) -> Expression {
+    match expr {
+      Expression::UnaryOp { op, arg } => Expression::UnaryOp { op, arg: self.boxed_expression(*arg) },
+      Expression::BinaryOp { lhs, op, rhs } => Expression::BinaryOp {
+        lhs: self.boxed_expression(*lhs), op, rhs: self.boxed_expression(*rhs)
+      },
+      Expression::VariableAccess(name) => Expression::VariableAccess(self.variable(name)),
+      Expression::FunctionCall { name, args } => Expression::FunctionCall {
+        name: self.function(name),
+        args: args.into_iter().map(|arg| self.expression(arg)).collect()
+      },
+      Expression::TableFunction { name, table } => Expression::TableFunction { name, table: self.table(table) },
+      Expression::TableAccess { table, index } => Expression::TableAccess {
+        table: self.table(table),
+        index: self.boxed_expression(*index)
+      },
+      Expression::Conditional { cond, then, otherwise } => Expression::Conditional {
+        cond: self.boxed_expression(*cond), then: self.boxed_expression(*then), otherwise: self.boxed_expression(*otherwise)
+      },
+      Expression::NumericLiteral(n) => Expression::NumericLiteral(n)
+    }
+  }
+}
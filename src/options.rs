@@ -0,0 +1,90 @@
+use std::collections::HashSet;
+
+// Implementation used for the ^ operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowImplementation {
+  // Extended precision logarithm and polynomial exponential, within 1 ulp
+  Accurate,
+  // exp(y * log(x)) with the regular math library, loses precision as |y * log(x)| grows
+  Fast,
+  // The original CORDIC based implementation
+  Cordic
+}
+
+// Warnings reported by the lints, each can be turned on with --warn=name and off with --allow=name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+  UnusedLocals,
+  UnusedFunctions,
+  Shadowing,
+  ImplicitGlobals,
+  UnreachableCode,
+  ConstantConditions
+}
+
+impl Lint {
+  pub const ALL: [Lint; 6] = [
+    Lint::UnusedLocals, Lint::UnusedFunctions, Lint::Shadowing, Lint::ImplicitGlobals,
+    Lint::UnreachableCode, Lint::ConstantConditions
+  ];
+  pub fn name(&self) -> &'static str {
+    match self {
+      Lint::UnusedLocals => "unused-locals",
+      Lint::UnusedFunctions => "unused-functions",
+      Lint::Shadowing => "shadowing",
+      Lint::ImplicitGlobals => "implicit-globals",
+      Lint::UnreachableCode => "unreachable-code",
+      Lint::ConstantConditions => "constant-conditions"
+    }
+  }
+  fn from_name(name: &str) -> Lint {
+    *Lint::ALL.iter().find(|lint| lint.name() == name).unwrap_or_else(|| panic!("Unknown lint {}", name))
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompilerOptions {
+  pub pow: PowImplementation,
+  // Name the function running the top level statements is exported with, if it is exported
+  pub entry: Option<String>,
+  // Runs the top level statements from the start section when the module is instantiated
+  pub start: bool,
+  // Lints that are reported, all of them unless allowed
+  pub lints: HashSet<Lint>
+}
+
+impl Default for CompilerOptions {
+  fn default() -> Self {
+    CompilerOptions { pow: PowImplementation::Accurate, entry: Some("#main".to_string()), start: false,
+      lints: Lint::ALL.into_iter().collect()
+    }
+  }
+}
+
+impl CompilerOptions {
+  pub fn parse_flag(&mut self, flag: &str) {
+    match flag {
+      "--pow=accurate" => self.pow = PowImplementation::Accurate,
+      "--pow=fast" => self.pow = PowImplementation::Fast,
+      "--pow=cordic" => self.pow = PowImplementation::Cordic,
+      "--no-entry" => self.entry = None,
+      "--start" => self.start = true,
+      _ => if let Some(entry) = flag.strip_prefix("--entry=") {
+        self.entry = Some(entry.to_string())
+      } else if let Some(lint) = flag.strip_prefix("--warn=") {
+        self.lints.insert(Lint::from_name(lint));
+      } else if let Some(lint) = flag.strip_prefix("--allow=") {
+        self.lints.remove(&Lint::from_name(lint));
+      } else {
+        panic!("Unknown option {}", flag)
+      }
+    }
+  }
+  pub fn pow_polyfill(&self) -> &'static str {
+    match self.pow {
+      PowImplementation::Accurate => "#pow",
+      PowImplementation::Fast => "#pow_fast",
+      PowImplementation::Cordic => "#pow_cordic"
+    }
+  }
+}
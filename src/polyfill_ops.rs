@@ -1,8 +1,10 @@
 use std::vec;
 
 use crate::jsonlang;
-use crate::variable_analysis::{ AnalysisResults, Function, UnaryOp, self };
-use crate::cordic::cordic_polyfill::generate_exp_polyfills;
+use crate::variable_analysis::{ AnalysisResults, BinaryIntrinsic, Function, UnaryOp, self };
+use crate::polyfill_registry::{ Polyfill, PolyfillBody, link_polyfills };
+use crate::options::CompilerOptions;
+use crate::strength_reduction::{ reduce_power, reduce_multiplication, reduce_division };
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -25,19 +27,33 @@ pub enum BinaryOp {
   GreaterEq,
   Equal,
   NotEqual,
-  Modulo
+  Min,
+  Max,
+  CopySign
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Statement {
-  Loop(Vec<Statement>),
+  // Continue jumps to the step
+  Loop {
+    body: Vec<Statement>,
+    step: Vec<Statement>
+  },
   If {
     cond: Expression,
     then: Vec<Statement>,
     otherwise: Vec<Statement>
   },
-  Break,
-  Continue,
+  // Runs cases[targets[index]], or the default when the index is past the targets
+  Switch {
+    index: Expression,
+    targets: Vec<u32>,
+    cases: Vec<Vec<Statement>>,
+    default: Vec<Statement>
+  },
+  // Number of loops around the innermost one that are also left
+  Break(u32),
+  Continue(u32),
   LocalSet(u32, Expression),
   GlobalSet(u32, Expression),
   Call(u32, Vec<Expression>),
@@ -56,8 +72,17 @@ pub enum Expression {
     rhs: Box<Expression>
   },
   LocalGet(u32),
+  // Sets the local and evaluates to the value, only produced by strength reduction
+  LocalTee(u32, Box<Expression>),
   GlobalGet(u32),
   FunctionCall(u32, Vec<Expression>),
+  TableGet(u32, Box<Expression>),
+  // The cond is an i32, the values are f64
+  Conditional {
+    cond: Box<Expression>,
+    then: Box<Expression>,
+    otherwise: Box<Expression>
+  },
   NumericLiteral (f64)
 }
 
@@ -67,21 +92,112 @@ fn float_to_int(arg: Expression) -> Expression {
 fn int_to_float(arg: Expression) -> Expression {
   Expression::UnaryOp { op: UnaryOp::IntToFloat, arg: Box::new(arg) }
 }
+fn binop(lhs: Expression, op: BinaryOp, rhs: Expression) -> Expression {
+  Expression::BinaryOp { lhs: Box::new(lhs), op, rhs: Box::new(rhs) }
+}
+fn unop(op: UnaryOp, arg: Expression) -> Expression {
+  Expression::UnaryOp { op, arg: Box::new(arg) }
+}
 
-impl AnalysisResults<Statement> {
-  fn get_polyfill_index(&mut self, name: &str) -> u32 {
-    if self.funcname_map.contains_key(name) {
-      *self.funcname_map.get(name).unwrap()
-    } else {
-      let index = self.funcname_map.len() as u32;
-      self.funcname_map.insert(name.to_string(), index);
-      index
+fn logic_or_polyfill() -> Function<Statement> {
+  Function {
+    arguments: 2, local_count: 0,
+    body: vec![Statement::If {
+      cond: float_to_int(Expression::LocalGet(0)),
+      then: vec![Statement::Return(Expression::LocalGet(0))],
+      otherwise: vec![Statement::Return(Expression::LocalGet(1))],
+    }]
+  }
+}
+
+// Floating point remainder with the semantics of C's fmod: the result has the sign of
+// the dividend and is exact. NaN is returned for NaN operands, infinite dividends and
+// zero divisors, and finite dividends are returned unchanged when the divisor is infinite.
+// The remainder is computed by long division, subtracting the divisor scaled by powers
+// of two, so every subtraction is exact and it takes at most ~2100 iterations.
+fn fmod_polyfill() -> Function<Statement> {
+  let (x, y, r, d, t) = (|| Expression::LocalGet(0), || Expression::LocalGet(1),
+    || Expression::LocalGet(2), || Expression::LocalGet(3), || Expression::LocalGet(4));
+  let is_nan = |e: Expression| binop(e.clone(), BinaryOp::NotEqual, e);
+  Function {
+    arguments: 2, local_count: 3,
+    body: vec![
+      Statement::If {
+        cond: binop(
+          binop(is_nan(x()), BinaryOp::BitwiseOr, is_nan(y())),
+          BinaryOp::BitwiseOr,
+          binop(
+            binop(y(), BinaryOp::Equal, Expression::NumericLiteral(0.0)),
+            BinaryOp::BitwiseOr,
+            binop(unop(UnaryOp::Abs, x()), BinaryOp::Equal, Expression::NumericLiteral(f64::INFINITY))
+          )
+        ),
+        then: vec![Statement::Return(Expression::NumericLiteral(f64::NAN))],
+        otherwise: vec![]
+      },
+      Statement::LocalSet(2, unop(UnaryOp::Abs, x())),
+      Statement::LocalSet(3, unop(UnaryOp::Abs, y())),
+      Statement::If {
+        cond: binop(r(), BinaryOp::Lesser, d()),
+        then: vec![Statement::Return(x())],
+        otherwise: vec![]
+      },
+      Statement::LocalSet(4, d()),
+      Statement::Loop { body: vec![Statement::If {
+        cond: binop(binop(t(), BinaryOp::Multiplication, Expression::NumericLiteral(2.0)), BinaryOp::LessEq, r()),
+        then: vec![Statement::LocalSet(4, binop(t(), BinaryOp::Multiplication, Expression::NumericLiteral(2.0)))],
+        otherwise: vec![Statement::Break(0)]
+      }], step: vec![] },
+      Statement::Loop { body: vec![Statement::If {
+        cond: binop(t(), BinaryOp::GreaterEq, d()),
+        then: vec![
+          Statement::If {
+            cond: binop(r(), BinaryOp::GreaterEq, t()),
+            then: vec![Statement::LocalSet(2, binop(r(), BinaryOp::Substraction, t()))],
+            otherwise: vec![]
+          },
+          Statement::LocalSet(4, binop(t(), BinaryOp::Division, Expression::NumericLiteral(2.0)))
+        ],
+        otherwise: vec![Statement::Break(0)]
+      }], step: vec![] },
+      Statement::If {
+        cond: binop(x(), BinaryOp::Lesser, Expression::NumericLiteral(0.0)),
+        then: vec![Statement::Return(unop(UnaryOp::NumericNegation, r()))],
+        otherwise: vec![Statement::Return(r())]
+      }
+    ]
+  }
+}
+
+pub const BUILTIN_POLYFILLS: &[Polyfill] = &[
+  Polyfill { name: "#logic_or", arguments: 2, dependencies: &[], body: PolyfillBody::Ir(logic_or_polyfill) },
+  Polyfill { name: "#fmod", arguments: 2, dependencies: &[], body: PolyfillBody::Ir(fmod_polyfill) }
+];
+
+// The function being polyfilled
+struct Scope<'a> {
+  options: &'a CompilerOptions,
+  arguments: u32,
+  local_count: u32,
+  // Local shared by every strength reduction in the function, added after its own locals
+  temp: Option<u32>
+}
+
+impl Scope<'_> {
+  fn temp(&mut self) -> u32 {
+    if self.temp.is_none() {
+      self.temp = Some(self.arguments + self.local_count);
+      self.local_count += 1;
     }
+    self.temp.unwrap()
   }
-  fn polyfill_binop(&mut self, op: jsonlang::BinaryOp, lhs: variable_analysis::Expression, rhs: variable_analysis::Expression) -> Expression {
-    let mut box_and_poly = |expr| Box::new(self.add_polyfills_to_expression(expr));
+}
+
+impl AnalysisResults<Statement> {
+  fn polyfill_binop(&mut self, op: jsonlang::BinaryOp, lhs: variable_analysis::Expression, rhs: variable_analysis::Expression, scope: &mut Scope) -> Expression {
+    let mut box_and_poly = |expr| Box::new(self.add_polyfills_to_expression(expr, scope));
     let mut leave_unchanged = |lhs, op, rhs| Expression::BinaryOp { 
-      lhs: box_and_poly(lhs), op: op, rhs: box_and_poly(rhs)
+      lhs: box_and_poly(lhs), op, rhs: box_and_poly(rhs)
     };
     let zero_if_zero = |expr| Box::new(int_to_float(Expression::BinaryOp {
       lhs: expr,
@@ -91,11 +207,24 @@ impl AnalysisResults<Statement> {
     match op {
       jsonlang::BinaryOp::Addition => leave_unchanged(lhs, BinaryOp::Addition, rhs),
       jsonlang::BinaryOp::Substraction => leave_unchanged(lhs, BinaryOp::Substraction, rhs),
-      jsonlang::BinaryOp::Multiplication => leave_unchanged(lhs, BinaryOp::Multiplication, rhs),
-      jsonlang::BinaryOp::Division => leave_unchanged(lhs, BinaryOp::Division, rhs),
-      jsonlang::BinaryOp::Exponentiation => Expression::FunctionCall(self.get_polyfill_index("#pow"),
-        vec![self.add_polyfills_to_expression(lhs), self.add_polyfills_to_expression(rhs)]),
-      jsonlang::BinaryOp::Modulo => leave_unchanged(lhs, BinaryOp::Modulo, rhs),
+      jsonlang::BinaryOp::Multiplication => reduce_multiplication(self.add_polyfills_to_expression(lhs, scope),
+        self.add_polyfills_to_expression(rhs, scope)),
+      jsonlang::BinaryOp::Division => reduce_division(self.add_polyfills_to_expression(lhs, scope),
+        self.add_polyfills_to_expression(rhs, scope)),
+      jsonlang::BinaryOp::Exponentiation => {
+        let base = self.add_polyfills_to_expression(lhs, scope);
+        let base = match rhs {
+          variable_analysis::Expression::NumericLiteral(exponent) => match reduce_power(base, exponent, &mut || scope.temp()) {
+            Ok(power) => return power,
+            Err(base) => base
+          },
+          _ => base
+        };
+        let exponent = self.add_polyfills_to_expression(rhs, scope);
+        Expression::FunctionCall(self.get_polyfill_index(scope.options.pow_polyfill()), vec![base, exponent])
+      },
+      jsonlang::BinaryOp::Modulo => Expression::FunctionCall(self.get_polyfill_index("#fmod"),
+        vec![self.add_polyfills_to_expression(lhs, scope), self.add_polyfills_to_expression(rhs, scope)]),
       jsonlang::BinaryOp::BitwiseAnd => leave_unchanged(lhs, BinaryOp::BitwiseAnd, rhs),
       jsonlang::BinaryOp::BitwiseOr => leave_unchanged(lhs, BinaryOp::BitwiseOr, rhs),
       jsonlang::BinaryOp::RightShift => leave_unchanged(lhs, BinaryOp::RightShift, rhs),
@@ -112,80 +241,91 @@ impl AnalysisResults<Statement> {
         rhs: box_and_poly(rhs)
       },
       jsonlang::BinaryOp::LogicalOr => Expression::FunctionCall(self.get_polyfill_index("#logic_or"),
-        vec![self.add_polyfills_to_expression(lhs), self.add_polyfills_to_expression(rhs)])
+        vec![self.add_polyfills_to_expression(lhs, scope), self.add_polyfills_to_expression(rhs, scope)])
     }
   }
 
-  fn add_polyfills_to_expression(&mut self, expr: variable_analysis::Expression) -> Expression {
+  fn add_polyfills_to_expression(&mut self, expr: variable_analysis::Expression, scope: &mut Scope) -> Expression {
     match expr {
-      variable_analysis::Expression::UnaryOp { op, arg } => Expression::UnaryOp { op: op, arg: Box::new(self.add_polyfills_to_expression(*arg)) },
-      variable_analysis::Expression::BinaryOp { lhs, op, rhs } => self.polyfill_binop(op, *lhs, *rhs),
+      variable_analysis::Expression::UnaryOp { op, arg } => Expression::UnaryOp { op, arg: Box::new(self.add_polyfills_to_expression(*arg, scope)) },
+      variable_analysis::Expression::BinaryOp { lhs, op, rhs } => self.polyfill_binop(op, *lhs, *rhs, scope),
+      variable_analysis::Expression::BinaryIntrinsic { lhs, op, rhs } => Expression::BinaryOp {
+        lhs: Box::new(self.add_polyfills_to_expression(*lhs, scope)),
+        op: match op {
+          BinaryIntrinsic::Min => BinaryOp::Min,
+          BinaryIntrinsic::Max => BinaryOp::Max,
+          BinaryIntrinsic::CopySign => BinaryOp::CopySign
+        },
+        rhs: Box::new(self.add_polyfills_to_expression(*rhs, scope))
+      },
       variable_analysis::Expression::LocalGet(index) => Expression::LocalGet(index),
       variable_analysis::Expression::GlobalGet(index) => Expression::GlobalGet(index),
       variable_analysis::Expression::FunctionCall(index, args) => Expression::FunctionCall(index,
-        args.into_iter().map(|e| self.add_polyfills_to_expression(e)).collect()),
+        args.into_iter().map(|e| self.add_polyfills_to_expression(e, scope)).collect()),
+      variable_analysis::Expression::TableGet(table, index) => Expression::TableGet(table,
+        Box::new(self.add_polyfills_to_expression(*index, scope))),
+      variable_analysis::Expression::Conditional { cond, then, otherwise } => Expression::Conditional {
+        cond: Box::new(self.add_polyfills_to_expression(*cond, scope)),
+        then: Box::new(self.add_polyfills_to_expression(*then, scope)),
+        otherwise: Box::new(self.add_polyfills_to_expression(*otherwise, scope))
+      },
       variable_analysis::Expression::NumericLiteral(n) => Expression::NumericLiteral(n)
     }
   }
 
-  fn add_polyfills_to_statement(&mut self, stmt: variable_analysis::Statement) -> Statement {
-    let fix_vec = |s: &mut Self, list: Vec<variable_analysis::Statement>| list.into_iter()
-      .map(|e| s.add_polyfills_to_statement(e)).collect();
+  fn add_polyfills_to_statement(&mut self, stmt: variable_analysis::Statement, scope: &mut Scope) -> Statement {
+    let fix_vec = |s: &mut Self, list: Vec<variable_analysis::Statement>, scope: &mut Scope| list.into_iter()
+      .map(|e| s.add_polyfills_to_statement(e, scope)).collect();
     match stmt {
-      variable_analysis::Statement::Loop(body) => Statement::Loop(fix_vec(self, body)),
+      variable_analysis::Statement::Loop { body, step } => Statement::Loop {
+        body: fix_vec(self, body, scope),
+        step: fix_vec(self, step, scope)
+      },
       variable_analysis::Statement::If { cond, then, otherwise } => Statement::If {
-        cond: self.add_polyfills_to_expression(cond),
-        then: fix_vec(self, then),
-        otherwise: fix_vec(self, otherwise)
+        cond: self.add_polyfills_to_expression(cond, scope),
+        then: fix_vec(self, then, scope),
+        otherwise: fix_vec(self, otherwise, scope)
       },
-      variable_analysis::Statement::LocalSet(index, expr) => Statement::LocalSet(index, self.add_polyfills_to_expression(expr)),
-      variable_analysis::Statement::GlobalSet(index, expr) => Statement::GlobalSet(index, self.add_polyfills_to_expression(expr)),
+      variable_analysis::Statement::LocalSet(index, expr) => Statement::LocalSet(index, self.add_polyfills_to_expression(expr, scope)),
+      variable_analysis::Statement::GlobalSet(index, expr) => Statement::GlobalSet(index, self.add_polyfills_to_expression(expr, scope)),
       variable_analysis::Statement::Call(index, exprs) => Statement::Call(index,
-        exprs.into_iter().map(|e| self.add_polyfills_to_expression(e)).collect()),
-      variable_analysis::Statement::Return(expr) => Statement::Return(self.add_polyfills_to_expression(expr)),
-      variable_analysis::Statement::Break => Statement::Break,
-      variable_analysis::Statement::Continue => Statement::Continue
+        exprs.into_iter().map(|e| self.add_polyfills_to_expression(e, scope)).collect()),
+      variable_analysis::Statement::Return(expr) => Statement::Return(self.add_polyfills_to_expression(expr, scope)),
+      variable_analysis::Statement::Switch { index, targets, cases, default } => Statement::Switch {
+        index: self.add_polyfills_to_expression(index, scope),
+        targets,
+        cases: cases.into_iter().map(|case| fix_vec(self, case, scope)).collect(),
+        default: fix_vec(self, default, scope)
+      },
+      variable_analysis::Statement::Break(depth) => Statement::Break(depth),
+      variable_analysis::Statement::Continue(depth) => Statement::Continue(depth)
     }
   }
-  pub fn polyfill_ops(program: AnalysisResults<variable_analysis::Statement>) -> Self {
+  pub fn polyfill_ops(program: AnalysisResults<variable_analysis::Statement>, options: &CompilerOptions) -> Self {
     let mut res = AnalysisResults {
       global_variables: program.global_variables,
+      global_declarations: program.global_declarations,
       funcname_map: program.funcname_map,
-      functions: std::collections::HashMap::new()
+      functions: std::collections::HashMap::new(),
+      tables: program.tables,
+      exports: program.exports
     };
     res.functions = program.functions.into_iter().map(|(index, func)| {
-      (index, Function {
-        arguments: func.arguments,
-        local_count: func.local_count,
-        body: func.body.into_iter().map(|stmt| res.add_polyfills_to_statement(stmt)).collect()
-      })
+      let mut scope = Scope { options, arguments: func.arguments, local_count: func.local_count, temp: None };
+      let body = func.body.into_iter().map(|stmt| res.add_polyfills_to_statement(stmt, &mut scope)).collect();
+      (index, Function { arguments: func.arguments, local_count: scope.local_count, body })
     }).collect();
-
-    if let Some(index) = res.funcname_map.get("#logic_or") {
-      res.functions.insert(*index, Function {
-        arguments: 2, local_count: 0,
-        body: vec![Statement::If {
-          cond: float_to_int(Expression::LocalGet(0)),
-          then: vec![Statement::Return(Expression::LocalGet(0))],
-          otherwise: vec![Statement::Return(Expression::LocalGet(1))],
-        }]
-      });
-    }
-    if res.funcname_map.contains_key("#pow") {
-      let index = *res.funcname_map.get("#pow").unwrap();
-      let (cordic_pow, lut_table, cordic_ln, cordic_exp) = generate_exp_polyfills(index);
-      res.funcname_map.insert("#cordic_lut".to_string(), index + 1);
-      res.funcname_map.insert("#ln_cordic".to_string(), index + 2);
-      res.funcname_map.insert("#exp_cordic".to_string(), index + 3);
-      res.functions.insert(index, cordic_pow);
-      res.functions.insert(index + 1, lut_table);
-      res.functions.insert(index + 2, cordic_ln);
-      res.functions.insert(index + 3, cordic_exp);
-    }
     res
   }
 }
 
-pub fn polyfill_ops(program: AnalysisResults<variable_analysis::Statement>) -> AnalysisResults<Statement> {
-  AnalysisResults::polyfill_ops(program)
+// Replaces the operations wasm doesn't have with polyfill calls, without adding the polyfills
+pub fn unlinked_polyfill_ops(program: AnalysisResults<variable_analysis::Statement>, options: &CompilerOptions) -> AnalysisResults<Statement> {
+  AnalysisResults::polyfill_ops(program, options)
+}
+
+pub fn polyfill_ops(program: AnalysisResults<variable_analysis::Statement>, options: &CompilerOptions) -> AnalysisResults<Statement> {
+  let mut res = unlinked_polyfill_ops(program, options);
+  link_polyfills(&mut res);
+  res
 }
@@ -0,0 +1,148 @@
+use crate::variable_analysis::{ AnalysisResults, Function };
+use crate::polyfill_ops::{ Statement, Expression, BUILTIN_POLYFILLS };
+use crate::cordic::cordic_polyfill::CORDIC_POLYFILLS;
+use crate::math::math_polyfill::{ MATH_BUILTINS, MATH_POLYFILLS };
+use crate::stdlib::stdlib_polyfill::{ STDLIB_BUILTINS, STDLIB_POLYFILLS };
+
+use crate::libraries::{ library_function, library_table };
+
+// A JSONLang program whose functions are used as polyfill bodies, lowered by build.rs
+pub struct Library {
+  pub name: &'static str,
+  // Only read by build.rs
+  #[allow(dead_code)]
+  pub source: &'static str
+}
+
+pub enum PolyfillBody {
+  // Calls and table reads inside the body use the position of the callee or table in `dependencies` as index
+  Ir(fn() -> Function<Statement>),
+  // The library function with the polyfill name minus the leading '#'
+  JsonLang(&'static Library)
+}
+
+pub struct Polyfill {
+  pub name: &'static str,
+  pub arguments: u32,
+  // Polyfills and library tables used by the body
+  pub dependencies: &'static [&'static str],
+  pub body: PolyfillBody
+}
+
+// Index of a function or table used by a polyfill body
+pub enum Reference {
+  Call(u32),
+  Table(u32)
+}
+
+pub fn find_polyfill(name: &str) -> Option<&'static Polyfill> {
+  BUILTIN_POLYFILLS.iter().chain(CORDIC_POLYFILLS).chain(MATH_POLYFILLS).chain(STDLIB_POLYFILLS)
+    .find(|polyfill| polyfill.name == name)
+}
+
+pub fn is_polyfill(name: &str) -> bool {
+  find_polyfill(name).is_some()
+}
+
+// Library functions that programs can call by name when they don't define a function with that name
+pub fn is_library_builtin(name: &str) -> bool {
+  MATH_BUILTINS.contains(&name) || STDLIB_BUILTINS.contains(&name)
+}
+
+// Adds the body of every polyfill referenced by the program, along with everything they depend on.
+// Polyfills are given the next free function index and every call inside them is relocated.
+pub fn link_polyfills(program: &mut AnalysisResults<Statement>) {
+  let mut pending: Vec<String> = program.funcname_map.keys().filter(|name| is_polyfill(name)).cloned().collect();
+  while let Some(name) = pending.pop() {
+    let index = program.get_polyfill_index(&name);
+    if program.functions.contains_key(&index) {
+      continue
+    }
+    let polyfill = find_polyfill(&name).unwrap_or_else(|| panic!("Unknown polyfill {}", name));
+    let func = match polyfill.body {
+      PolyfillBody::Ir(body) => body(),
+      PolyfillBody::JsonLang(library) => library_function(library, &name)
+    };
+    if func.arguments != polyfill.arguments {
+      panic!("Polyfill {} takes {} arguments, expected {}", name, func.arguments, polyfill.arguments)
+    }
+    let func = relocate_calls(polyfill, func, &mut |reference| match reference {
+      Reference::Call(dependency) => {
+        let name = polyfill.dependencies[dependency as usize];
+        pending.push(name.to_string());
+        program.get_polyfill_index(name)
+      },
+      Reference::Table(dependency) => {
+        let name = polyfill.dependencies[dependency as usize];
+        program.get_table_index(name).unwrap_or_else(|| match polyfill.body {
+          PolyfillBody::JsonLang(library) => program.add_table(name.to_string(), library_table(library, name)),
+          PolyfillBody::Ir(_) => panic!("Polyfill {} can't use tables", polyfill.name)
+        })
+      }
+    });
+    program.functions.insert(index, func);
+  }
+}
+
+// Gives every call and table read inside the body of a polyfill the index returned by `relocate`
+pub fn relocate_calls(polyfill: &Polyfill, func: Function<Statement>, relocate: &mut dyn FnMut(Reference) -> u32) -> Function<Statement> {
+  let mut relocator = Relocator { polyfill, relocate };
+  let body = func.body.into_iter().map(|stmt| relocator.relocate_stmt(stmt)).collect();
+  Function { arguments: func.arguments, local_count: func.local_count, body }
+}
+
+struct Relocator<'a> {
+  polyfill: &'a Polyfill,
+  relocate: &'a mut dyn FnMut(Reference) -> u32
+}
+
+impl Relocator<'_> {
+  fn relocate_call(&mut self, index: u32) -> u32 {
+    (self.relocate)(Reference::Call(index))
+  }
+  fn relocate_stmt(&mut self, stmt: Statement) -> Statement {
+    let map_vec = |s: &mut Self, vec: Vec<Statement>| vec.into_iter().map(|stmt| s.relocate_stmt(stmt)).collect();
+    match stmt {
+      Statement::Loop { body, step } => Statement::Loop { body: map_vec(self, body), step: map_vec(self, step) },
+      Statement::If { cond, then, otherwise } => Statement::If {
+        cond: self.relocate_expr(cond),
+        then: map_vec(self, then), otherwise: map_vec(self, otherwise)
+      },
+      Statement::LocalSet(index, expr) => Statement::LocalSet(index, self.relocate_expr(expr)),
+      Statement::GlobalSet(_, _) => panic!("Polyfill {} can't use globals", self.polyfill.name),
+      Statement::Call(index, args) => Statement::Call(self.relocate_call(index),
+        args.into_iter().map(|e| self.relocate_expr(e)).collect()),
+      Statement::Return(expr) => Statement::Return(self.relocate_expr(expr)),
+      Statement::Switch { index, targets, cases, default } => Statement::Switch {
+        index: self.relocate_expr(index),
+        targets,
+        cases: cases.into_iter().map(|case| map_vec(self, case)).collect(),
+        default: map_vec(self, default)
+      },
+      Statement::Break(depth) => Statement::Break(depth),
+      Statement::Continue(depth) => Statement::Continue(depth)
+    }
+  }
+  fn relocate_expr(&mut self, expr: Expression) -> Expression {
+    match expr {
+      Expression::UnaryOp { op, arg } => Expression::UnaryOp { op, arg: Box::new(self.relocate_expr(*arg)) },
+      Expression::BinaryOp { lhs, op, rhs } => Expression::BinaryOp {
+        lhs: Box::new(self.relocate_expr(*lhs)), op, rhs: Box::new(self.relocate_expr(*rhs))
+      },
+      Expression::FunctionCall(index, args) => {
+        let index = self.relocate_call(index);
+        Expression::FunctionCall(index, args.into_iter().map(|e| self.relocate_expr(e)).collect())
+      }
+      Expression::LocalTee(index, value) => Expression::LocalTee(index, Box::new(self.relocate_expr(*value))),
+      Expression::TableGet(table, index) => {
+        let table = (self.relocate)(Reference::Table(table));
+        Expression::TableGet(table, Box::new(self.relocate_expr(*index)))
+      },
+      Expression::Conditional { cond, then, otherwise } => Expression::Conditional {
+        cond: Box::new(self.relocate_expr(*cond)), then: Box::new(self.relocate_expr(*then)), otherwise: Box::new(self.relocate_expr(*otherwise))
+      },
+      Expression::GlobalGet(_) => panic!("Polyfill {} can't use globals", self.polyfill.name),
+      _ => expr
+    }
+  }
+}
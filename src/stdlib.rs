@@ -0,0 +1 @@
+pub mod stdlib_polyfill;
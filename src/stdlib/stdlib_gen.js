@@ -0,0 +1,101 @@
+const fs = require("fs");
+
+const op = (binop, argl, argr) => ({ binop, argl, argr });
+const neg = (arg) => ({ unop: "-", arg });
+const call = (name, ...args) => ({ call: name, args });
+const declare = (name, value) => ({ declare: name, value });
+const set = (name, value) => ({ set: name, value });
+const ret = (value) => ({ "return": value });
+const when = (cond, then, otherwise) => otherwise === undefined
+  ? { "if": [{ cond, then }] }
+  : { "if": [{ cond, then }], "else": otherwise };
+const loop = (cond, body) => ({ "while": cond, "do": body });
+const abs = (x) => call("abs", x);
+// Only infinities and NaN give something else than 0
+const isFinite = (x) => op("==", op("-", x, x), 0);
+
+let program = [];
+const fn = (name, args, block) => program.push({ "function": name, args, block });
+
+fn("clamp", ["x", "low", "high"], [ret(call("min", call("max", "x", "low"), "high"))]);
+
+fn("sign", ["x"], [
+  when(op(">", "x", 0), [ret(1)]),
+  when(op("<", "x", 0), [ret(-1)]),
+  // 0, -0 and NaN are their own sign
+  ret("x")
+]);
+
+fn("lerp", ["a", "b", "t"], [ret(op("+", "a", op("*", op("-", "b", "a"), "t")))]);
+
+// Euclid's algorithm on the magnitudes
+fn("gcd", ["a", "b"], [
+  set("a", abs("a")),
+  set("b", abs("b")),
+  loop(op("~=", "b", 0), [declare("t", op("%", "a", "b")), set("a", "b"), set("b", "t")]),
+  ret("a")
+]);
+
+// Divides before multiplying so that the result only overflows when it doesn't fit
+fn("lcm", ["a", "b"], [
+  when(op("|", op("==", "a", 0), op("==", "b", 0)), [ret(0)]),
+  ret(abs(op("*", op("/", "a", call("gcd", "a", "b")), "b")))
+]);
+
+fn("factorial", ["n"], [
+  declare("r", 1),
+  { "iterator": "i", "from": 2, "to": "n", "do": [set("r", op("*", "r", "i"))] },
+  ret("r")
+]);
+
+fn("is_nan", ["x"], [ret(op("~=", "x", "x"))]);
+
+fn("is_finite", ["x"], [ret(isFinite("x"))]);
+
+// Ties go away from zero
+fn("round", ["x"], [
+  declare("t", call("trunc", "x")),
+  when(op(">=", abs(op("-", "x", "t")), 0.5), [ret(op("+", "t", call("copysign", 1, "x")))]),
+  ret("t")
+]);
+
+fn("radians", ["x"], [ret(op("*", "x", Math.PI / 180))]);
+
+fn("degrees", ["x"], [ret(op("*", "x", 180 / Math.PI))]);
+
+fn("fraction", ["x"], [ret(op("-", "x", call("trunc", "x")))]);
+
+// 10^n for integer n, the count is capped since anything past 10^400 is already infinite
+fn("pow10", ["n"], [
+  declare("r", 1),
+  declare("k", call("min", abs(call("trunc", "n")), 400)),
+  loop(op(">", "k", 0), [set("r", op("*", "r", 10)), set("k", op("-", "k", 1))]),
+  when(op("<", "n", 0), [ret(op("/", 1, "r"))]),
+  ret("r")
+]);
+
+fn("round_to", ["x", "decimals"], [
+  declare("p", call("pow10", "decimals")),
+  ret(op("/", call("round", op("*", "x", "p")), "p"))
+]);
+
+// Digits of the integer part, NaN for infinities and NaN
+fn("digit_count", ["x"], [
+  when(op("~=", op("-", "x", "x"), 0), [ret(op("/", 0, 0))]),
+  declare("n", call("floor", abs("x"))),
+  declare("count", 1),
+  declare("p", 10),
+  loop(op("<=", "p", "n"), [set("count", op("+", "count", 1)), set("p", op("*", "p", 10))]),
+  ret("count")
+]);
+
+// Digit of 10^position, negative positions are decimals
+fn("digit", ["x", "position"], [
+  declare("shifted", abs("x")),
+  when(op("<", "position", 0),
+    [set("shifted", op("*", "shifted", call("pow10", neg("position"))))],
+    [set("shifted", op("/", "shifted", call("pow10", "position")))]),
+  ret(op("%", call("floor", "shifted"), 10))
+]);
+
+fs.writeFileSync("stdlib.lang.json", JSON.stringify(program, null, 1));
@@ -0,0 +1,32 @@
+use crate::polyfill_registry::{ Library, Polyfill, PolyfillBody };
+
+// General purpose functions callable from JSONLang, implemented in stdlib.lang.json (generated by stdlib_gen.js).
+pub const STDLIB_BUILTINS: [&str; 16] = [
+  "clamp", "sign", "lerp", "gcd", "lcm", "factorial", "is_nan", "is_finite", "round", "radians", "degrees",
+  "fraction", "pow10", "round_to", "digit_count", "digit"
+];
+
+pub const STDLIB_LIBRARY: Library = Library { name: "stdlib", source: include_str!("stdlib.lang.json") };
+
+const fn stdlib(name: &'static str, arguments: u32, dependencies: &'static [&'static str]) -> Polyfill {
+  Polyfill { name, arguments, dependencies, body: PolyfillBody::JsonLang(&STDLIB_LIBRARY) }
+}
+
+pub const STDLIB_POLYFILLS: &[Polyfill] = &[
+  stdlib("#clamp", 3, &[]),
+  stdlib("#sign", 1, &[]),
+  stdlib("#lerp", 3, &[]),
+  stdlib("#gcd", 2, &["#fmod"]),
+  stdlib("#lcm", 2, &["#gcd"]),
+  stdlib("#factorial", 1, &[]),
+  stdlib("#is_nan", 1, &[]),
+  stdlib("#is_finite", 1, &[]),
+  stdlib("#round", 1, &[]),
+  stdlib("#radians", 1, &[]),
+  stdlib("#degrees", 1, &[]),
+  stdlib("#fraction", 1, &[]),
+  stdlib("#pow10", 1, &[]),
+  stdlib("#round_to", 2, &["#pow10", "#round"]),
+  stdlib("#digit_count", 1, &[]),
+  stdlib("#digit", 2, &["#pow10", "#fmod"])
+];
@@ -0,0 +1,80 @@
+use crate::variable_analysis::UnaryOp;
+use crate::polyfill_ops::{ Expression, BinaryOp };
+
+fn binop(lhs: Expression, op: BinaryOp, rhs: Expression) -> Expression {
+  Expression::BinaryOp { lhs: Box::new(lhs), op, rhs: Box::new(rhs) }
+}
+fn unop(op: UnaryOp, arg: Expression) -> Expression {
+  Expression::UnaryOp { op, arg: Box::new(arg) }
+}
+
+// Expressions that can be evaluated more than once instead of going through a local
+fn is_trivial(expr: &Expression) -> bool {
+  matches!(expr, Expression::LocalGet(_) | Expression::GlobalGet(_) | Expression::NumericLiteral(_))
+}
+
+// Whether x / c == x * (1 / c) for every x, which holds when c is a power of two with an exact inverse
+fn has_exact_inverse(c: f64) -> bool {
+  c.is_normal() && c.to_bits() & ((1 << 52) - 1) == 0
+}
+
+// x ^ exponent for a constant exponent, the base is given back if it still needs a #pow polyfill.
+// Bases that can't be evaluated twice go through `temp`, a local that is only used between the
+// local.tee of a fully evaluated value and its local.get's, so nested powers can share it.
+// Exponents whose result is rounded once give the correctly rounded power. x^3 rounds twice, but the error of
+// x * x only moves the product by less than 1 ulp, within the 1 ulp of #pow. x^4 and longer chains can be 2 ulp
+// or more away and x^-n can underflow before the division, so they are left to #pow.
+pub fn reduce_power(base: Expression, exponent: f64, temp: &mut dyn FnMut() -> u32) -> Result<Expression, Expression> {
+  // The value itself, to be evaluated first, and an expression that reads it again afterwards
+  let mut share = |value: Expression| {
+    if is_trivial(&value) {
+      (value.clone(), value)
+    } else {
+      let index = temp();
+      (Expression::LocalTee(index, Box::new(value)), Expression::LocalGet(index))
+    }
+  };
+  match exponent {
+    // 1 even for NaN, the base is still evaluated
+    0.0 if is_trivial(&base) => Ok(Expression::NumericLiteral(1.0)),
+    0.0 => Ok(unop(UnaryOp::Abs, binop(Expression::NumericLiteral(1.0), BinaryOp::CopySign, base))),
+    1.0 => Ok(base),
+    -1.0 => Ok(binop(Expression::NumericLiteral(1.0), BinaryOp::Division, base)),
+    2.0 => {
+      let (x, again) = share(base);
+      Ok(binop(x, BinaryOp::Multiplication, again))
+    },
+    3.0 => {
+      let (x, again) = share(base);
+      Ok(binop(binop(x, BinaryOp::Multiplication, again.clone()), BinaryOp::Multiplication, again))
+    },
+    // sqrt gives -0 for -0 and NaN for -inf, where pow gives +0 and +inf
+    0.5 => {
+      let (x, again) = share(base);
+      Ok(Expression::Conditional {
+        cond: Box::new(binop(x, BinaryOp::Equal, Expression::NumericLiteral(f64::NEG_INFINITY))),
+        then: Box::new(Expression::NumericLiteral(f64::INFINITY)),
+        otherwise: Box::new(unop(UnaryOp::Abs, unop(UnaryOp::Sqrt, again)))
+      })
+    },
+    _ => Err(base)
+  }
+}
+
+// Cheaper exact forms of lhs * rhs: multiplications by 1 and -1 disappear
+pub fn reduce_multiplication(lhs: Expression, rhs: Expression) -> Expression {
+  match (lhs, rhs) {
+    (Expression::NumericLiteral(c), expr) | (expr, Expression::NumericLiteral(c)) if c == 1.0 => expr,
+    (Expression::NumericLiteral(c), expr) | (expr, Expression::NumericLiteral(c)) if c == -1.0 =>
+      unop(UnaryOp::NumericNegation, expr),
+    (lhs, rhs) => binop(lhs, BinaryOp::Multiplication, rhs)
+  }
+}
+
+// Cheaper exact forms of lhs / rhs: divisions by powers of two become multiplications by their inverse
+pub fn reduce_division(lhs: Expression, rhs: Expression) -> Expression {
+  match rhs {
+    Expression::NumericLiteral(c) if has_exact_inverse(c) => reduce_multiplication(lhs, Expression::NumericLiteral(1.0 / c)),
+    rhs => binop(lhs, BinaryOp::Division, rhs)
+  }
+}
@@ -1,18 +1,31 @@
 use std::collections::HashMap;
-use crate::jsonlang::{ BinaryOp, self };
+use crate::jsonlang::{ BinaryOp, Export, self };
 use crate::control_simplification::{ SimplifiedStatement, SimplifiedTopStatement };
+use crate::polyfill_registry::{ find_polyfill, is_library_builtin, is_polyfill };
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Statement {
-  Loop(Vec<Statement>),
+  // Continue jumps to the step
+  Loop {
+    body: Vec<Statement>,
+    step: Vec<Statement>
+  },
   If {
     cond: Expression,
     then: Vec<Statement>,
     otherwise: Vec<Statement>
   },
-  Break,
-  Continue,
+  // Runs cases[targets[index]], or the default when the index is past the targets
+  Switch {
+    index: Expression,
+    targets: Vec<u32>,
+    cases: Vec<Vec<Statement>>,
+    default: Vec<Statement>
+  },
+  // Number of loops around the innermost one that are also left
+  Break(u32),
+  Continue(u32),
   LocalSet(u32, Expression),
   GlobalSet(u32, Expression),
   Call(u32, Vec<Expression>),
@@ -27,7 +40,19 @@ pub enum UnaryOp {
   FloatToInt,
   IntToFloat,
   Sqrt,
-  Floor
+  Floor,
+  Ceil,
+  Trunc,
+  Nearest,
+  Abs
+}
+
+// Builtins with two operands emitted as a single f64 instruction
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum BinaryIntrinsic {
+  Min,
+  Max,
+  CopySign
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -41,23 +66,63 @@ pub enum Expression {
     op: BinaryOp,
     rhs: Box<Expression>
   },
+  BinaryIntrinsic {
+    lhs: Box<Expression>,
+    op: BinaryIntrinsic,
+    rhs: Box<Expression>
+  },
   LocalGet(u32),
   GlobalGet(u32),
   FunctionCall(u32, Vec<Expression>),
+  // Element of a table, by table index
+  TableGet(u32, Box<Expression>),
+  // Only the chosen value is evaluated
+  Conditional {
+    cond: Box<Expression>,
+    then: Box<Expression>,
+    otherwise: Box<Expression>
+  },
   NumericLiteral (f64)
 }
 
+// Builtins emitted as a single f64 instruction instead of a call
+fn unary_intrinsic(name: &str) -> Option<UnaryOp> {
+  match name {
+    "abs" => Some(UnaryOp::Abs),
+    "ceil" => Some(UnaryOp::Ceil),
+    "floor" => Some(UnaryOp::Floor),
+    "trunc" => Some(UnaryOp::Trunc),
+    "nearest" => Some(UnaryOp::Nearest),
+    "sqrt" => Some(UnaryOp::Sqrt),
+    _ => None
+  }
+}
+
+fn binary_intrinsic(name: &str) -> Option<BinaryIntrinsic> {
+  match name {
+    "min" => Some(BinaryIntrinsic::Min),
+    "max" => Some(BinaryIntrinsic::Max),
+    "copysign" => Some(BinaryIntrinsic::CopySign),
+    _ => None
+  }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct VariableStore {
   index: u32,
-  stores: Vec<HashMap<String, u32>>
+  stores: Vec<HashMap<String, u32>>,
+  // Values of the constants declared in each block
+  constants: Vec<HashMap<String, f64>>
 }
 
 impl VariableStore {
   pub fn new() -> Self {
-    VariableStore { index: 0, stores: vec![HashMap::new()] }
+    VariableStore { index: 0, stores: vec![HashMap::new()], constants: vec![HashMap::new()] }
   }
   pub fn declare(&mut self, name: String) -> u32 {
+    if self.constants.last().unwrap().contains_key(&name) {
+      panic!("{} is already declared as a constant", name)
+    }
     let index = self.index;
     self.stores.last_mut().unwrap().insert(name, index);
     self.index += 1;
@@ -71,6 +136,24 @@ impl VariableStore {
     }
     false
   }
+  pub fn declare_constant(&mut self, name: String, value: f64) {
+    if self.stores.last().unwrap().contains_key(&name) || self.constants.last().unwrap().contains_key(&name) {
+      panic!("{} is already declared", name)
+    }
+    self.constants.last_mut().unwrap().insert(name, value);
+  }
+  // Value of the constant a name refers to, unless a variable in an inner block shadows it
+  pub fn constant(&self, name: &String) -> Option<f64> {
+    for (store, constants) in self.stores.iter().zip(self.constants.iter()).rev() {
+      if store.contains_key(name) {
+        return None
+      }
+      if let Some(value) = constants.get(name) {
+        return Some(*value)
+      }
+    }
+    None
+  }
   pub fn declare_or_get(&mut self, name: String) -> u32 {
     if self.has(&name) {
       self.get_id(name)
@@ -79,10 +162,12 @@ impl VariableStore {
     }
   }
   pub fn enter_block(&mut self) {
-    self.stores.push(HashMap::new())
+    self.stores.push(HashMap::new());
+    self.constants.push(HashMap::new());
   }
   pub fn exit_block(&mut self) {
     self.stores.pop();
+    self.constants.pop();
   }
   pub fn get_id(&self, name: String) -> u32 {
     for store in self.stores.iter().rev() {
@@ -93,7 +178,7 @@ impl VariableStore {
     panic!("Variable not found")
   }
   pub fn count(&self) -> u32 {
-    return self.index
+    self.index
   }
   pub fn list_variables(&self) -> Vec<(&String, &u32)> {
     self.stores.iter().flat_map(|s: &HashMap<String, u32>| s.iter()).collect()
@@ -107,11 +192,42 @@ pub struct Function<T> {
   pub body: Vec<T>
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Table {
+  pub name: String,
+  pub values: Vec<f64>
+}
+
+// Name each exported function and global is exported with, by index
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Exports {
+  pub functions: HashMap<u32, String>,
+  pub globals: HashMap<u32, String>
+}
+
+// Initial value and mutability of a declared global, the others start at 0 and are mutable
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Global {
+  pub value: f64,
+  pub mutable: bool
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AnalysisResults<T> {
   pub global_variables: VariableStore,
+  pub global_declarations: HashMap<u32, Global>,
   pub funcname_map: HashMap<String, u32>,
-  pub functions: HashMap<u32, Function<T>>
+  pub functions: HashMap<u32, Function<T>>,
+  pub tables: Vec<Table>,
+  pub exports: Exports
+}
+
+fn export_name(name: &str, export: Option<&Export>) -> Option<String> {
+  match export {
+    None | Some(Export::Public(true)) => Some(name.to_string()),
+    Some(Export::Public(false)) => None,
+    Some(Export::Renamed(export)) => Some(export.clone())
+  }
 }
 
 impl<T> AnalysisResults<T> {
@@ -122,36 +238,237 @@ impl<T> AnalysisResults<T> {
     let index = self.funcname_map.len() as u32;
     self.funcname_map.insert(name.clone(), index);
   }
+  pub fn get_table_index(&self, name: &str) -> Option<u32> {
+    self.tables.iter().position(|table| table.name == name).map(|index| index as u32)
+  }
+  pub fn add_table(&mut self, name: String, values: Vec<f64>) -> u32 {
+    if self.get_table_index(&name).is_some() {
+      panic!("Duplicate table definition found")
+    }
+    self.tables.push(Table { name, values });
+    self.tables.len() as u32 - 1
+  }
+  pub fn get_polyfill_index(&mut self, name: &str) -> u32 {
+    if self.funcname_map.contains_key(name) {
+      *self.funcname_map.get(name).unwrap()
+    } else {
+      let index = self.funcname_map.len() as u32;
+      self.funcname_map.insert(name.to_string(), index);
+      index
+    }
+  }
+  fn get_function_index(&mut self, name: &String) -> u32 {
+    if let Some(index) = self.funcname_map.get(name) {
+      *index
+    } else if is_library_builtin(name) {
+      self.get_polyfill_index(&format!("#{}", name))
+    } else if name.starts_with('#') && is_polyfill(name) {
+      // Polyfill libraries call each other by the polyfill name
+      self.get_polyfill_index(name)
+    } else if unary_intrinsic(name).is_some() || binary_intrinsic(name).is_some() {
+      panic!("Result of {} must be used", name)
+    } else {
+      panic!("Unknown function called")
+    }
+  }
 }
 
 impl AnalysisResults<Statement> {
   pub fn new() -> Self {
-    return AnalysisResults {
+    AnalysisResults {
       global_variables: VariableStore::new(),
+      global_declarations: HashMap::new(),
       funcname_map: HashMap::new(),
-      functions: HashMap::new()
+      functions: HashMap::new(),
+      tables: Vec::new(),
+      exports: Exports { functions: HashMap::new(), globals: HashMap::new() }
     }
   }
   pub fn analyze_top(&mut self, program: Vec<SimplifiedTopStatement>) {
     let mut top_statements: Vec<SimplifiedStatement> = Vec::new();
+    let mut function_exports = HashMap::new();
+    let mut global_exports = HashMap::new();
+    let mut top_scopes = VariableStore::new();
     for statement in program.iter() {
       match statement {
-        SimplifiedTopStatement::Statement(_) => (),
-        SimplifiedTopStatement::DeclarationStatement { name, .. } => self.allocate_index(name.clone())
+        // Top level declarations are globals, so functions can use them
+        SimplifiedTopStatement::Statement(SimplifiedStatement::Declare(name, _)) => {
+          self.global_variables.declare_or_get(name.clone());
+        },
+        SimplifiedTopStatement::Statement(SimplifiedStatement::Const(name, value)) => {
+          let value = self.evaluate_constant(value, &VariableStore::new());
+          self.global_variables.declare_constant(name.clone(), value);
+        },
+        SimplifiedTopStatement::Statement(stmt) => self.declare_set_globals(stmt, &mut top_scopes),
+        SimplifiedTopStatement::DeclarationStatement { name, args, content, export } => {
+          self.allocate_index(name.clone());
+          function_exports.insert(name.clone(), export.clone());
+          let mut scopes = VariableStore::new();
+          args.iter().for_each(|arg| { scopes.declare(arg.clone()); });
+          self.declare_set_globals(content, &mut scopes);
+        },
+        SimplifiedTopStatement::Global { name, value, mutable, export } => {
+          if global_exports.insert(name.clone(), export.clone()).is_some() {
+            panic!("Duplicate global declaration {}", name)
+          }
+          let value = value.as_ref().map_or(0.0, |value| self.evaluate_constant(value, &VariableStore::new()));
+          let index = self.global_variables.declare_or_get(name.clone());
+          self.global_declarations.insert(index, Global { value, mutable: *mutable });
+        },
+        SimplifiedTopStatement::Table { name, values } => {
+          // Linked library tables are named after their polyfill dependency
+          if name.starts_with('#') {
+            panic!("Table name {} is reserved for the libraries", name)
+          }
+          self.add_table(name.clone(), values.clone());
+        }
       }
     }
+    self.check_arity(&program);
     for statement in program {
       match statement {
+        SimplifiedTopStatement::Statement(SimplifiedStatement::Declare(name, expr)) => {
+          top_statements.push(SimplifiedStatement::Set(name, expr))
+        },
+        SimplifiedTopStatement::Statement(SimplifiedStatement::Const(..)) => (),
         SimplifiedTopStatement::Statement(stmt) => {
           top_statements.push(stmt)
         },
-        SimplifiedTopStatement::DeclarationStatement { name, args, content } => {
+        SimplifiedTopStatement::DeclarationStatement { name, args, content, .. } => {
           self.analyze_function(name, args, content)
+        },
+        SimplifiedTopStatement::Global { .. } | SimplifiedTopStatement::Table { .. } => ()
+      }
+    }
+    // The exported name of #main and the start section are up to codegen
+    if !top_statements.is_empty() {
+      self.allocate_index("#main".to_string());
+      self.analyze_function("#main".to_string(), Vec::new(), SimplifiedStatement::Block(top_statements));
+    }
+    self.add_exports(function_exports, global_exports);
+  }
+  // Sets of names that aren't locals create globals, they exist before any function is analyzed
+  // so that updates anywhere can tell them apart from undefined names
+  fn declare_set_globals(&mut self, stmt: &SimplifiedStatement, scopes: &mut VariableStore) {
+    match stmt {
+      SimplifiedStatement::Loop { body, step } => {
+        self.declare_set_globals(body, scopes);
+        self.declare_set_globals(step, scopes);
+      },
+      SimplifiedStatement::If { then, otherwise, .. } => {
+        self.declare_set_globals(then, scopes);
+        self.declare_set_globals(otherwise, scopes);
+      },
+      SimplifiedStatement::Switch { cases, default, .. } => cases.iter().chain([&**default]).for_each(|stmt| self.declare_set_globals(stmt, scopes)),
+      SimplifiedStatement::Declare(name, _) => {
+        scopes.declare(name.clone());
+      },
+      // Only whether a name is a constant matters here, not its value
+      SimplifiedStatement::Const(name, _) => scopes.declare_constant(name.clone(), 0.0),
+      SimplifiedStatement::Set(name, _) if !scopes.has(name) && self.constant(name, scopes).is_none() => {
+        self.global_variables.declare_or_get(name.clone());
+      },
+      SimplifiedStatement::Block(block) => {
+        scopes.enter_block();
+        block.iter().for_each(|stmt| self.declare_set_globals(stmt, scopes));
+        scopes.exit_block();
+      },
+      _ => ()
+    }
+  }
+  // Calls must pass as many arguments as the function takes, wasm only finds out when validating
+  fn check_arity(&self, program: &[SimplifiedTopStatement]) {
+    let mut declared = HashMap::new();
+    let mut bodies = Vec::new();
+    for statement in program {
+      match statement {
+        SimplifiedTopStatement::DeclarationStatement { name, args, content, .. } => {
+          declared.insert(name, args.len());
+          bodies.push((format!("function {}", name), content));
+        },
+        SimplifiedTopStatement::Statement(stmt) => bodies.push(("the top level code".to_string(), stmt)),
+        SimplifiedTopStatement::Global { .. } | SimplifiedTopStatement::Table { .. } => ()
+      }
+    }
+    let mut errors = Vec::new();
+    for (caller, body) in bodies {
+      let mut calls = Vec::new();
+      statement_calls(body, &mut calls);
+      for (name, args) in calls {
+        let expected = declared.get(name).copied()
+          .or_else(|| unary_intrinsic(name).map(|_| 1))
+          .or_else(|| binary_intrinsic(name).map(|_| 2))
+          .or_else(|| if is_library_builtin(name) { find_polyfill(&format!("#{}", name)) } else { find_polyfill(name) }
+            .map(|polyfill| polyfill.arguments as usize));
+        if let Some(expected) = expected.filter(|expected| *expected != args.len()) {
+          let call = jsonlang::Expression::FunctionCall { name: name.clone(), args: args.clone() };
+          errors.push(format!("{} takes {} arguments but the call {} in {} passes {}",
+            name.trim_start_matches('#'), expected, call, caller, args.len()));
         }
       }
     }
-    self.allocate_index("#main".to_string());
-    self.analyze_function("#main".to_string(), Vec::new(), SimplifiedStatement::Block(top_statements))
+    if !errors.is_empty() {
+      panic!("{}", errors.join("\n"))
+    }
+  }
+  // Every function and global is exported unless declared otherwise, polyfills never are
+  fn add_exports(&mut self, function_exports: HashMap<String, Option<Export>>, global_exports: HashMap<String, Option<Export>>) {
+    let mut exported = std::collections::HashSet::new();
+    let mut check_unique = |name: &String| if !exported.insert(name.clone()) {
+      panic!("Duplicate export {}", name)
+    };
+    for (name, index) in self.funcname_map.iter().filter(|(name, _)| !is_polyfill(name) && *name != "#main") {
+      if let Some(export) = export_name(name, function_exports.get(name).and_then(Option::as_ref)) {
+        check_unique(&export);
+        self.exports.functions.insert(*index, export);
+      }
+    }
+    for (name, index) in self.global_variables.list_variables() {
+      if let Some(export) = export_name(name, global_exports.get(name).and_then(Option::as_ref)) {
+        check_unique(&export);
+        self.exports.globals.insert(*index, export);
+      }
+    }
+  }
+  // Value of the constant a name refers to, local constants and variables shadow global ones
+  fn constant(&self, name: &String, locals: &VariableStore) -> Option<f64> {
+    locals.constant(name).or_else(|| if locals.has(name) { None } else { self.global_variables.constant(name) })
+  }
+  // Tables can't change, so functions of all their values are constants
+  fn table_function(&self, name: &str, table: &str) -> f64 {
+    let index = self.get_table_index(table).unwrap_or_else(|| panic!("Unknown table {}", table));
+    let values = &self.tables[index as usize].values;
+    match name {
+      "table_length" => values.len() as f64,
+      "table_sum" => values.iter().sum(),
+      "table_min" => values.iter().copied().fold(f64::INFINITY, f64::min),
+      "table_max" => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+      _ => panic!("Unknown table function {}", name)
+    }
+  }
+  // Values of constants can use numbers, other constants, functions of tables and arithmetic
+  fn evaluate_constant(&self, expr: &jsonlang::Expression, locals: &VariableStore) -> f64 {
+    match expr {
+      jsonlang::Expression::NumericLiteral(value) => *value,
+      jsonlang::Expression::VariableAccess(name) => self.constant(name, locals)
+        .unwrap_or_else(|| panic!("{} isn't a constant", name)),
+      jsonlang::Expression::UnaryOp { op: jsonlang::UnaryOp::NumericNegation, arg } => -self.evaluate_constant(arg, locals),
+      jsonlang::Expression::TableFunction { name, table } => self.table_function(name, table),
+      jsonlang::Expression::BinaryOp { lhs, op, rhs } => {
+        let lhs = self.evaluate_constant(lhs, locals);
+        let rhs = self.evaluate_constant(rhs, locals);
+        match op {
+          BinaryOp::Addition => lhs + rhs,
+          BinaryOp::Substraction => lhs - rhs,
+          BinaryOp::Multiplication => lhs * rhs,
+          BinaryOp::Division => lhs / rhs,
+          BinaryOp::Modulo => lhs % rhs,
+          BinaryOp::Exponentiation => lhs.powf(rhs),
+          _ => panic!("Constants can only use +, -, *, /, % and ^")
+        }
+      },
+      _ => panic!("Constant value isn't known at compile time")
+    }
   }
   fn analyze_function(&mut self, name: String, args: Vec<String>, content: SimplifiedStatement) {
     let index = *self.funcname_map.get(&name).unwrap();
@@ -170,27 +487,85 @@ impl AnalysisResults<Statement> {
   fn analyze_variables(&mut self, stmt: SimplifiedStatement, locals: &mut VariableStore) -> Vec<Statement> {
     let mut processed = Vec::new();
     match stmt {
-      SimplifiedStatement::Loop(content) => processed.push(Statement::Loop(self.analyze_variables(*content, locals))),
+      SimplifiedStatement::Loop { body, step } => processed.push(Statement::Loop {
+        body: self.analyze_variables(*body, locals),
+        step: self.analyze_variables(*step, locals)
+      }),
       SimplifiedStatement::If { cond, then, otherwise } => processed.push(Statement::If {
         cond: self.translate_expression(cond, locals),
         then: self.analyze_variables(*then, locals),
         otherwise: self.analyze_variables(*otherwise, locals)
       }),
-      SimplifiedStatement::Break => processed.push(Statement::Break),
-      SimplifiedStatement::Continue => processed.push(Statement::Continue),
+      SimplifiedStatement::Switch { value, labels, cases, default } => {
+        let values: Vec<f64> = labels.iter().map(|label| self.evaluate_constant(label, locals)).collect();
+        for (i, value) in values.iter().enumerate() {
+          if values[..i].contains(value) {
+            panic!("Duplicate case {} in switch", value)
+          }
+        }
+        let Some((min, targets)) = jump_table(&values) else {
+          // Sparse cases are a chain of comparisons
+          let get = || Box::new(jsonlang::Expression::VariableAccess(value.clone()));
+          let chain = values.into_iter().zip(cases).rev().fold(*default, |otherwise, (case, body)| SimplifiedStatement::If {
+            cond: jsonlang::Expression::BinaryOp { lhs: get(), op: BinaryOp::Equal, rhs: Box::new(jsonlang::Expression::NumericLiteral(case)) },
+            then: Box::new(body),
+            otherwise: Box::new(otherwise)
+          });
+          return self.analyze_variables(chain, locals)
+        };
+        let local = locals.get_id(value);
+        let get = || Box::new(Expression::LocalGet(local));
+        let binop = |lhs, op, rhs| Box::new(Expression::BinaryOp { lhs, op, rhs });
+        let number = |n| Box::new(Expression::NumericLiteral(n));
+        let past_end = min + targets.len() as f64;
+        // Values that aren't one of the integers in the table go to the default, past its end
+        let in_table = binop(
+          binop(binop(get(), BinaryOp::GreaterEq, number(min)), BinaryOp::LogicalAnd, binop(get(), BinaryOp::Lesser, number(past_end))),
+          BinaryOp::LogicalAnd,
+          binop(get(), BinaryOp::Equal, Box::new(Expression::UnaryOp { op: UnaryOp::Floor, arg: get() }))
+        );
+        processed.push(Statement::If {
+          cond: Expression::UnaryOp { op: UnaryOp::LogicNegation, arg: in_table },
+          then: vec![Statement::LocalSet(local, Expression::NumericLiteral(past_end))],
+          otherwise: vec![]
+        });
+        processed.push(Statement::Switch {
+          index: *binop(get(), BinaryOp::Substraction, number(min)),
+          targets,
+          cases: cases.into_iter().map(|case| self.analyze_variables(case, locals)).collect(),
+          default: self.analyze_variables(*default, locals)
+        })
+      },
+      SimplifiedStatement::Break(depth) => processed.push(Statement::Break(depth)),
+      SimplifiedStatement::Continue(depth) => processed.push(Statement::Continue(depth)),
       SimplifiedStatement::Declare(name, expr) => {
         let index = locals.declare(name);
         processed.push(Statement::LocalSet(index, self.translate_expression(expr, locals)))
       }
-      SimplifiedStatement::Set(name, expr) => {
+      SimplifiedStatement::Const(name, expr) => {
+        let value = self.evaluate_constant(&expr, locals);
+        locals.declare_constant(name, value);
+      }
+      SimplifiedStatement::Update(name, _) if self.constant(&name, locals).is_none()
+        && !locals.has(&name) && !self.global_variables.has(&name) => {
+        panic!("Can't update {}, it is never declared or set", name)
+      }
+      SimplifiedStatement::Set(name, expr) | SimplifiedStatement::Update(name, expr) => {
+        if self.constant(&name, locals).is_some() {
+          panic!("Can't set constant {}", name)
+        }
         processed.push(if locals.has(&name) {
           Statement::LocalSet(locals.get_id(name), self.translate_expression(expr, locals))
         } else {
-          Statement::GlobalSet(self.global_variables.declare_or_get(name), self.translate_expression(expr, locals))
+          let index = self.global_variables.declare_or_get(name.clone());
+          if self.global_declarations.get(&index).is_some_and(|global| !global.mutable) {
+            panic!("Global {} is immutable", name)
+          }
+          Statement::GlobalSet(index, self.translate_expression(expr, locals))
         })
       }
       SimplifiedStatement::Call(name, args) => {
-        let index = *self.funcname_map.get(&name).expect("Unknown function called");
+        let index = self.get_function_index(&name);
         let mut translated_args = Vec::new();
         for arg in args {
           translated_args.push(self.translate_expression(arg, locals))
@@ -223,27 +598,109 @@ impl AnalysisResults<Statement> {
         rhs: Box::new(self.translate_expression(*rhs, locals))
       },
       jsonlang::Expression::VariableAccess(name) => {
-        if locals.has(&name) {
+        if let Some(value) = self.constant(&name, locals) {
+          Expression::NumericLiteral(value)
+        } else if locals.has(&name) {
           Expression::LocalGet(locals.get_id(name))
         } else {
-          Expression::GlobalGet(self.global_variables.get_id(name))
+          // Undefined names were already reported, the set creating this global may come later
+          Expression::GlobalGet(self.global_variables.declare_or_get(name))
         }
       },
+      jsonlang::Expression::FunctionCall { name, args } if !self.funcname_map.contains_key(&name)
+        && (unary_intrinsic(&name).is_some() || binary_intrinsic(&name).is_some()) => {
+        let mut args = args.into_iter().map(|arg| self.translate_expression(arg, locals));
+        // The number of arguments was already checked
+        let mut next_arg = || Box::new(args.next().unwrap());
+        if let Some(op) = unary_intrinsic(&name) {
+          Expression::UnaryOp { op, arg: next_arg() }
+        } else {
+          Expression::BinaryIntrinsic { lhs: next_arg(), op: binary_intrinsic(&name).unwrap(), rhs: next_arg() }
+        }
+      }
       jsonlang::Expression::FunctionCall { name, args } => {
-        let index = *self.funcname_map.get(&name).expect("Unknown function called");
+        let index = self.get_function_index(&name);
         let mut translated_args = Vec::new();
         for arg in args {
           translated_args.push(self.translate_expression(arg, locals))
         }
         Expression::FunctionCall(index, translated_args)
       }
+      jsonlang::Expression::TableFunction { name, table } => Expression::NumericLiteral(self.table_function(&name, &table)),
+      jsonlang::Expression::TableAccess { table, index } => {
+        let table = self.get_table_index(&table).unwrap_or_else(|| panic!("Unknown table {}", table));
+        Expression::TableGet(table, Box::new(self.translate_expression(*index, locals)))
+      },
+      jsonlang::Expression::Conditional { cond, then, otherwise } => Expression::Conditional {
+        cond: Box::new(self.translate_expression(*cond, locals)),
+        then: Box::new(self.translate_expression(*then, locals)),
+        otherwise: Box::new(self.translate_expression(*otherwise, locals))
+      },
       jsonlang::Expression::NumericLiteral(n) => Expression::NumericLiteral(n)
     }
   }
 }
 
+// Smallest number of cases that goes through a jump table, and how much bigger than the number of cases the table can be
+const MIN_TABLE_CASES: usize = 3;
+const MAX_TABLE_SPREAD: f64 = 2.0;
+
+// Dense integer cases jump through a table that starts at the smallest case, each target is the position of the
+// case for that value, or the number of cases for the default
+fn jump_table(values: &[f64]) -> Option<(f64, Vec<u32>)> {
+  let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+  let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+  let dense = values.len() >= MIN_TABLE_CASES && values.iter().all(|value| value.fract() == 0.0)
+    && max - min + 1.0 <= MAX_TABLE_SPREAD * values.len() as f64;
+  dense.then(|| (min, (0..=(max - min) as u32).map(|offset| {
+    values.iter().position(|value| *value == min + offset as f64).unwrap_or(values.len()) as u32
+  }).collect()))
+}
+
+// Every call in a statement, with the called name and its arguments
+fn statement_calls<'a>(stmt: &'a SimplifiedStatement, calls: &mut Vec<(&'a String, &'a Vec<jsonlang::Expression>)>) {
+  match stmt {
+    SimplifiedStatement::Loop { body, step } => {
+      statement_calls(body, calls);
+      statement_calls(step, calls);
+    },
+    SimplifiedStatement::If { cond, then, otherwise } => {
+      expression_calls(cond, calls);
+      statement_calls(then, calls);
+      statement_calls(otherwise, calls);
+    },
+    SimplifiedStatement::Switch { cases, default, .. } => cases.iter().chain([&**default]).for_each(|stmt| statement_calls(stmt, calls)),
+    SimplifiedStatement::Break(_) | SimplifiedStatement::Continue(_) => (),
+    SimplifiedStatement::Declare(_, value) | SimplifiedStatement::Const(_, value) | SimplifiedStatement::Set(_, value)
+      | SimplifiedStatement::Update(_, value) | SimplifiedStatement::Return(value) => expression_calls(value, calls),
+    SimplifiedStatement::Call(name, args) => {
+      calls.push((name, args));
+      args.iter().for_each(|arg| expression_calls(arg, calls));
+    },
+    SimplifiedStatement::Block(stmts) => stmts.iter().for_each(|stmt| statement_calls(stmt, calls))
+  }
+}
+
+fn expression_calls<'a>(expr: &'a jsonlang::Expression, calls: &mut Vec<(&'a String, &'a Vec<jsonlang::Expression>)>) {
+  match expr {
+    jsonlang::Expression::UnaryOp { arg, .. } => expression_calls(arg, calls),
+    jsonlang::Expression::BinaryOp { lhs, rhs, .. } => {
+      expression_calls(lhs, calls);
+      expression_calls(rhs, calls);
+    },
+    jsonlang::Expression::FunctionCall { name, args } => {
+      calls.push((name, args));
+      args.iter().for_each(|arg| expression_calls(arg, calls));
+    },
+    jsonlang::Expression::TableAccess { index, .. } => expression_calls(index, calls),
+    jsonlang::Expression::TableFunction { .. } => (),
+    jsonlang::Expression::Conditional { cond, then, otherwise } => [cond, then, otherwise].into_iter().for_each(|expr| expression_calls(expr, calls)),
+    jsonlang::Expression::VariableAccess(_) | jsonlang::Expression::NumericLiteral(_) => ()
+  }
+}
+
 pub fn top_analyze_variables(program: Vec<SimplifiedTopStatement>) -> AnalysisResults<Statement> {
   let mut results = AnalysisResults::new();
   results.analyze_top(program);
-  return results
+  results
 }
@@ -24,12 +24,43 @@ function assertAlmostEquals(x, y, delta) {
   if (Math.abs(x - y) > delta) throw new Error(`Expected ${y} but found ${x}`);
 }
 
-function run(code) {
+function run(code, flags = "") {
   fs.writeFileSync("./test.jsonlang", JSON.stringify(code));
-  cp.execSync("cargo run test.jsonlang test.wat 2> /dev/null");
+  cp.execSync(`cargo run test.jsonlang test.wat ${flags} 2> /dev/null`);
   cp.execSync("wat2wasm test.wat -o test.wasm 2> /dev/null");
   return JSON.parse(cp.execSync("node runcode.js 2> /dev/null").toString())
 }
+// Compiles without running #main, for tests that need the exports
+function instantiate(code, flags = "") {
+  fs.writeFileSync("./test.jsonlang", JSON.stringify(code));
+  cp.execSync(`cargo run test.jsonlang test.wat ${flags} 2> /dev/null`);
+  cp.execSync("wat2wasm test.wat -o test.wasm 2> /dev/null");
+  return new WebAssembly.Instance(new WebAssembly.Module(fs.readFileSync("test.wasm"))).exports;
+}
+// Output of a compilation that is expected to fail
+function compileError(code, flags = "") {
+  fs.writeFileSync("./test.jsonlang", JSON.stringify(code));
+  try {
+    cp.execSync(`cargo run -q test.jsonlang test.wat ${flags}`, { stdio: "pipe" });
+  } catch (err) {
+    return err.stderr.toString();
+  }
+  throw new Error("Compiled without errors");
+}
+// Fails unless the compilation fails with each of the messages
+function expectError(code, ...messages) {
+  const err = compileError(code);
+  for (const message of messages) {
+    if (!err.includes(message)) throw new Error(`Missing ${message} in ${err}`);
+  }
+}
+// Warnings of a compilation that succeeds
+function compileWarnings(code, flags = "") {
+  fs.writeFileSync("./test.jsonlang", JSON.stringify(code));
+  const result = cp.spawnSync("cargo", ["run", "-q", "test.jsonlang", "test.wat", ...flags.split(" ").filter(flag => flag)]);
+  if (result.status != 0) throw new Error(result.stderr.toString());
+  return result.stderr.toString();
+}
 
 test("040", () => {
   const c = run([{
@@ -1890,6 +1921,2409 @@ test("023", () => {
   assertEquals(c, 2);
 });
 
+test("fmod", () => {
+  const c = run([{
+    "set": "out",
+    "value": {
+      "binop": "%",
+      "argl": 7.5,
+      "argr": 2,
+    },
+  }]);
+  assertEquals(c, 1.5);
+});
+
+test("fmod.negative", () => {
+  const lhs = run([{
+    "set": "out",
+    "value": {
+      "binop": "%",
+      "argl": {
+        "unop": "-",
+        "arg": 7.5,
+      },
+      "argr": 2,
+    },
+  }]);
+  assertEquals(lhs, -1.5);
+  const rhs = run([{
+    "set": "out",
+    "value": {
+      "binop": "%",
+      "argl": 7.5,
+      "argr": {
+        "unop": "-",
+        "arg": 2,
+      },
+    },
+  }]);
+  assertEquals(rhs, 1.5);
+});
+
+test("fmod.zero", () => {
+  const c = run([{
+    "set": "x",
+    "value": {
+      "binop": "%",
+      "argl": 5,
+      "argr": 0,
+    },
+  }, {
+    "set": "out",
+    "value": {
+      "binop": "~=",
+      "argl": "x",
+      "argr": "x",
+    },
+  }]);
+  assertEquals(c, 1);
+});
+
+test("fmod.infinity", () => {
+  const c = run([{
+    "set": "out",
+    "value": {
+      "binop": "%",
+      "argl": 3.25,
+      "argr": {
+        "binop": "/",
+        "argl": 1,
+        "argr": 0,
+      },
+    },
+  }]);
+  assertEquals(c, 3.25);
+});
+
+test("fmod.large", () => {
+  const c = run([{
+    "set": "out",
+    "value": {
+      "binop": "%",
+      "argl": 1e17,
+      "argr": 3,
+    },
+  }]);
+  assertEquals(c, 1e17 % 3);
+});
+
+test("math.trig", () => {
+  const c = run([{
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "binop": "^",
+        "argl": {
+          "call": "sin",
+          "args": [0.7],
+        },
+        "argr": 2,
+      },
+      "argr": {
+        "binop": "^",
+        "argl": {
+          "call": "cos",
+          "args": [0.7],
+        },
+        "argr": 2,
+      },
+    },
+  }]);
+  assertAlmostEquals(c, 1, 1e-9);
+});
+
+test("math.inverse", () => {
+  const c = run([{
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "call": "atan2",
+        "args": [1, {
+          "unop": "-",
+          "arg": 1,
+        }],
+      },
+      "argr": {
+        "call": "asin",
+        "args": [{
+          "call": "tan",
+          "args": [0.5],
+        }],
+      },
+    },
+  }]);
+  assertAlmostEquals(c, Math.atan2(1, -1) + Math.asin(Math.tan(0.5)), 1e-15);
+});
+
+test("math.exp", () => {
+  const c = run([{
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "call": "log",
+        "args": [{
+          "call": "exp",
+          "args": [3],
+        }],
+      },
+      "argr": {
+        "call": "log2",
+        "args": [1024],
+      },
+    },
+  }]);
+  assertAlmostEquals(c, 13, 1e-14);
+});
+
+test("math.hypot", () => {
+  const c = run([{
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "call": "hypot",
+        "args": [3, 4],
+      },
+      "argr": {
+        "call": "sqrt",
+        "args": [16],
+      },
+    },
+  }]);
+  assertEquals(c, 9);
+});
+
+test("math.override", () => {
+  const c = run([{
+    "function": "sqrt",
+    "args": ["x"],
+    "block": [{
+      "return": 7,
+    }],
+  }, {
+    "set": "out",
+    "value": {
+      "call": "sqrt",
+      "args": [16],
+    },
+  }]);
+  assertEquals(c, 7);
+});
+
+test("intrinsics.unary", () => {
+  const c = run([{
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "binop": "+",
+        "argl": {
+          "call": "floor",
+          "args": [2.5],
+        },
+        "argr": {
+          "call": "ceil",
+          "args": [2.5],
+        },
+      },
+      "argr": {
+        "binop": "+",
+        "argl": {
+          "binop": "+",
+          "argl": {
+            "call": "trunc",
+            "args": [{
+              "unop": "-",
+              "arg": 2.5,
+            }],
+          },
+          "argr": {
+            "call": "nearest",
+            "args": [2.5],
+          },
+        },
+        "argr": {
+          "binop": "+",
+          "argl": {
+            "call": "abs",
+            "args": [{
+              "unop": "-",
+              "arg": 10,
+            }],
+          },
+          "argr": {
+            "call": "sqrt",
+            "args": [400],
+          },
+        },
+      },
+    },
+  }]);
+  assertEquals(c, 35);
+});
+
+test("intrinsics.binary", () => {
+  const c = run([{
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "binop": "*",
+        "argl": {
+          "call": "min",
+          "args": [3, 7],
+        },
+        "argr": {
+          "call": "max",
+          "args": [3, 7],
+        },
+      },
+      "argr": {
+        "call": "copysign",
+        "args": [5, {
+          "unop": "-",
+          "arg": 1,
+        }],
+      },
+    },
+  }]);
+  assertEquals(c, 16);
+});
+
+test("intrinsics.condition", () => {
+  const c = run([{
+    "set": "out",
+    "value": 1,
+  }, {
+    "if": [{
+      "cond": {
+        "call": "floor",
+        "args": [0.5],
+      },
+      "then": [{
+        "set": "out",
+        "value": 2,
+      }],
+    }],
+  }]);
+  assertEquals(c, 1);
+});
+
+test("polyfills.combined", () => {
+  const c = run([{
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "binop": "or",
+        "argl": 0,
+        "argr": {
+          "binop": "^",
+          "argl": 2,
+          "argr": 3,
+        },
+      },
+      "argr": {
+        "binop": "+",
+        "argl": {
+          "binop": "%",
+          "argl": 7,
+          "argr": 4,
+        },
+        "argr": {
+          "call": "exp",
+          "args": [0],
+        },
+      },
+    },
+  }]);
+  assertAlmostEquals(c, 12, 1e-9);
+});
+
+test("pow.options", () => {
+  const code = [{
+    "set": "out",
+    "value": {
+      "binop": "^",
+      "argl": 1.5,
+      "argr": 2.5,
+    },
+  }];
+  assertEquals(run(code), Math.pow(1.5, 2.5));
+  assertEquals(run(code, "--pow=accurate"), Math.pow(1.5, 2.5));
+  assertAlmostEquals(run(code, "--pow=fast"), Math.pow(1.5, 2.5), 1e-12);
+  assertAlmostEquals(run(code, "--pow=cordic"), Math.pow(1.5, 2.5), 1e-6);
+});
+
+test("pow.constant", () => {
+  const c = run([{
+    "set": "x",
+    "value": 3,
+  }, {
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "binop": "^",
+        "argl": {
+          "binop": "+",
+          "argl": "x",
+          "argr": 1,
+        },
+        "argr": 2,
+      },
+      "argr": {
+        "binop": "+",
+        "argl": {
+          "binop": "^",
+          "argl": "x",
+          "argr": -1,
+        },
+        "argr": {
+          "binop": "^",
+          "argl": 16,
+          "argr": 0.5,
+        },
+      },
+    },
+  }]);
+  assertEquals(c, 16 + 1 / 3 + 4);
+  if (fs.readFileSync("test.wat").toString().includes("#pow")) throw new Error("#pow linked for constant exponents");
+  // pow gives +inf for -inf ^ 0.5, where sqrt gives NaN
+  const infinity = run([{
+    "set": "out",
+    "value": {
+      "binop": "==",
+      "argl": {
+        "binop": "^",
+        "argl": {
+          "binop": "/",
+          "argl": -1,
+          "argr": 0,
+        },
+        "argr": 0.5,
+      },
+      "argr": {
+        "binop": "/",
+        "argl": 1,
+        "argr": 0,
+      },
+    },
+  }]);
+  assertEquals(infinity, 1);
+  // x ^ 3 is a multiplication chain, longer chains round too much and use #pow
+  const power = exponent => [{
+    "set": "x",
+    "value": 3,
+  }, {
+    "set": "out",
+    "value": {
+      "binop": "^",
+      "argl": "x",
+      "argr": exponent,
+    },
+  }];
+  assertEquals(run(power(3)), 27);
+  if (fs.readFileSync("test.wat").toString().includes("#pow")) throw new Error("#pow linked for x ^ 3");
+  assertEquals(run(power(4)), 81);
+  if (!fs.readFileSync("test.wat").toString().includes("#pow")) throw new Error("x ^ 4 reduced to multiplications");
+});
+
+test("pow.dynamic", () => {
+  const c = run([{
+    "set": "y",
+    "value": 3,
+  }, {
+    "set": "out",
+    "value": {
+      "binop": "^",
+      "argl": 2,
+      "argr": "y",
+    },
+  }]);
+  assertEquals(c, 8);
+  const wat = fs.readFileSync("test.wat").toString();
+  if (!wat.includes("#pow")) throw new Error("#pow not linked");
+  // ln(i / 32) is read from the log tables of the math library
+  if (!wat.includes("f64.load") || wat.includes("#log_table")) throw new Error("Log tables not used by #pow");
+});
+
+test("pow.edge_cases", () => {
+  // The cases of POW_EDGE_CASES in math_polyfill.rs, whose tests run the IR in an interpreter, through the compiled module
+  const inf = Infinity, eps = Number.EPSILON;
+  const cases = [
+    [0, 0], [-0, -0], [NaN, 0], [inf, -0], [1, NaN], [1, inf], [NaN, 1], [2, NaN], [NaN, NaN], [-1, NaN],
+    [0, 3], [-0, 3], [-0, 2], [-0, 0.5], [0, -3], [-0, -3], [-0, -2], [-0, -0.5], [0, inf], [-0, -inf],
+    [-1, inf], [-1, -inf], [0.5, inf], [-0.5, -inf], [2, inf], [-2, -inf], [-2, inf],
+    [inf, 2], [inf, 0.5], [inf, -3], [-inf, 3], [-inf, 2], [-inf, 0.5], [-inf, -3], [-inf, -2], [-inf, -0.5],
+    [-2, 0.5], [-8, 1 / 3], [-2, 1e300], [-2, -1e300],
+    [2, 9], [3, 2], [-2, 3], [-2, -3], [-3, 4], [3, 20], [10, 22], [10, -2], [0.5, -10], [7, -3], [1.5, 5], [-1.1, 7],
+    [0.1, 3], [1e-10, 20], [1 + eps, 1e15]
+  ];
+  // Math.pow gives NaN for 1 ^ NaN and (-1) ^ inf, where IEEE 754 pow gives 1
+  const pow = (x, y) => x === 1 || (Math.abs(x) === 1 && Math.abs(y) === inf) ? 1 : Math.pow(x, y);
+  for (const flag of ["--pow=accurate", "--pow=fast", "--pow=cordic"]) {
+    const exports = instantiate([{
+      "function": "f",
+      "args": ["x", "y"],
+      "block": [{
+        "return": {
+          "binop": "^",
+          "argl": "x",
+          "argr": "y",
+        },
+      }],
+    }], flag);
+    for (const [x, y] of cases) {
+      const result = exports.f(x, y);
+      if (!Object.is(result, pow(x, y))) throw new Error(`${flag}: ${x} ^ ${y} is ${result}, expected ${pow(x, y)}`);
+    }
+  }
+});
+
+test("division.power_of_two", () => {
+  const c = run([{
+    "set": "x",
+    "value": 7,
+  }, {
+    "set": "out",
+    "value": {
+      "binop": "-",
+      "argl": {
+        "binop": "/",
+        "argl": "x",
+        "argr": 8,
+      },
+      "argr": {
+        "binop": "/",
+        "argl": "x",
+        "argr": 3,
+      },
+    },
+  }]);
+  assertEquals(c, 7 / 8 - 7 / 3);
+  const wat = fs.readFileSync("test.wat").toString();
+  if (!wat.includes("f64.const 0.125") || wat.split("f64.div").length != 2) throw new Error("Division by 8 not reduced");
+});
+
+test("import", () => {
+  fs.writeFileSync("./test_counter.jsonlang", JSON.stringify([{
+    "set": "count",
+    "value": 10,
+  }, {
+    "function": "next",
+    "args": [],
+    "block": [{
+      "set": "count",
+      "value": {
+        "binop": "+",
+        "argl": "count",
+        "argr": 1,
+      },
+    }, {
+      "return": "count",
+    }],
+  }]));
+  const c = run([{
+    "import": "test_counter.jsonlang",
+    "as": "counter",
+  }, {
+    "function": "next",
+    "args": [],
+    "block": [{
+      "return": 100,
+    }],
+  }, {
+    "set": "count",
+    "value": 1,
+  }, {
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "binop": "+",
+        "argl": {
+          "call": "counter.next",
+          "args": [],
+        },
+        "argr": {
+          "call": "next",
+          "args": [],
+        },
+      },
+      "argr": {
+        "binop": "+",
+        "argl": "counter.count",
+        "argr": "count",
+      },
+    },
+  }]);
+  cp.execSync("rm test_counter.jsonlang");
+  assertEquals(c, 11 + 100 + 11 + 1);
+});
+
+test("import.cycle", () => {
+  fs.writeFileSync("./test_cycle.jsonlang", JSON.stringify([{
+    "import": "test.jsonlang",
+  }]));
+  expectError([{
+    "import": "test_cycle.jsonlang",
+  }, {
+    "set": "out",
+    "value": 1,
+  }], "Import cycle");
+  cp.execSync("rm test_cycle.jsonlang");
+});
+
+test("table.functions", () => {
+  const c = run([{
+    "table": "primes",
+    "values": [2, 3, 5, 7, 11],
+  }, {
+    "const": "count",
+    "value": {
+      "call": "table_length",
+      "table": "primes",
+    },
+  }, {
+    "set": "out",
+    "value": 0,
+  }, {
+    "iterator": "i",
+    "from": 0,
+    "to": {
+      "binop": "-",
+      "argl": "count",
+      "argr": 1,
+    },
+    "do": {
+      "set": "out",
+      "value": {
+        "binop": "+",
+        "argl": "out",
+        "argr": {
+          "table": "primes",
+          "index": "i",
+        },
+      },
+    },
+  }, {
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "binop": "*",
+        "argl": "out",
+        "argr": 10000,
+      },
+      "argr": {
+        "binop": "+",
+        "argl": {
+          "binop": "*",
+          "argl": {
+            "call": "table_sum",
+            "table": "primes",
+          },
+          "argr": 100,
+        },
+        "argr": {
+          "binop": "-",
+          "argl": {
+            "call": "table_max",
+            "table": "primes",
+          },
+          "argr": {
+            "call": "table_min",
+            "table": "primes",
+          },
+        },
+      },
+    },
+  }]);
+  assertEquals(c, 28 * 10000 + 28 * 100 + 9);
+  // Function of a table from another file
+  fs.writeFileSync("./test_tables.jsonlang", JSON.stringify([{
+    "table": "sizes",
+    "values": [4, 8],
+  }]));
+  const imported = run([{
+    "import": "test_tables.jsonlang",
+    "as": "config",
+  }, {
+    "set": "out",
+    "value": {
+      "call": "table_sum",
+      "table": "config.sizes",
+    },
+  }]);
+  cp.execSync("rm test_tables.jsonlang");
+  assertEquals(imported, 12);
+  expectError([{
+    "table": "primes",
+    "values": [2],
+  }, {
+    "set": "out",
+    "value": {
+      "call": "table_average",
+      "table": "primes",
+    },
+  }], "Unknown table function table_average");
+});
+
+test("table.library_names", () => {
+  // Tables of the program don't replace the tables of the libraries
+  const program = name => [{
+    "table": name,
+    "values": [0, 0, 0],
+  }, {
+    "declare": "base",
+    "value": 1.7,
+  }, {
+    "set": "out",
+    "value": {
+      "binop": "^",
+      "argl": "base",
+      "argr": 2.5,
+    },
+  }];
+  assertAlmostEquals(run(program("log_table_hi")), Math.pow(1.7, 2.5), 1e-12);
+  expectError(program("#log_table_hi"), "Table name #log_table_hi is reserved for the libraries");
+});
+
+test("stdlib.math", () => {
+  const c = run([{
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "binop": "+",
+        "argl": {
+          "call": "gcd",
+          "args": [84, 36],
+        },
+        "argr": {
+          "call": "lcm",
+          "args": [4, 6],
+        },
+      },
+      "argr": {
+        "binop": "+",
+        "argl": {
+          "binop": "+",
+          "argl": {
+            "call": "clamp",
+            "args": [15, 0, 10],
+          },
+          "argr": {
+            "call": "factorial",
+            "args": [5],
+          },
+        },
+        "argr": {
+          "call": "round",
+          "args": [-2.5],
+        },
+      },
+    },
+  }]);
+  assertEquals(c, 12 + 12 + 10 + 120 - 3);
+  const wat = fs.readFileSync("test.wat").toString();
+  if (wat.includes("#digit") || wat.includes("#lerp")) throw new Error("Unused stdlib functions were linked");
+});
+
+test("stdlib.format", () => {
+  const c = run([{
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "binop": "+",
+        "argl": {
+          "binop": "*",
+          "argl": {
+            "call": "round_to",
+            "args": [3.14159, 2],
+          },
+          "argr": 100,
+        },
+        "argr": {
+          "call": "digit_count",
+          "args": [12345],
+        },
+      },
+      "argr": {
+        "binop": "+",
+        "argl": {
+          "binop": "*",
+          "argl": {
+            "call": "digit",
+            "args": [9876.5, 2],
+          },
+          "argr": 10,
+        },
+        "argr": {
+          "call": "digit",
+          "args": [9876.54, -1],
+        },
+      },
+    },
+  }]);
+  assertEquals(c, 314 + 5 + 80 + 5);
+});
+
+test("table", () => {
+  const c = run([{
+    "table": "primes",
+    "values": [2, 3, 5, 7, 11],
+  }, {
+    "table": "halves",
+    "values": [0.5, 0.25],
+  }, {
+    "function": "prime",
+    "args": ["n"],
+    "block": [{
+      "return": {
+        "table": "primes",
+        "index": "n",
+      },
+    }],
+  }, {
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "binop": "+",
+        "argl": {
+          "call": "prime",
+          "args": [4],
+        },
+        "argr": {
+          "call": "prime",
+          "args": [1.9],
+        },
+      },
+      "argr": {
+        "table": "halves",
+        "index": 1,
+      },
+    },
+  }]);
+  assertEquals(c, 11 + 3 + 0.25);
+  if (!fs.readFileSync("test.wat").toString().includes("f64.load offset=40")) throw new Error("Second table not after the first");
+});
+
+test("export", () => {
+  const c = run([{
+    "function": "helper",
+    "args": ["x"],
+    "export": false,
+    "block": [{
+      "return": {
+        "binop": "%",
+        "argl": "x",
+        "argr": 7,
+      },
+    }],
+  }, {
+    "function": "compute",
+    "args": ["x"],
+    "export": "public_compute",
+    "block": [{
+      "return": {
+        "call": "helper",
+        "args": ["x"],
+      },
+    }],
+  }, {
+    "global": "hidden",
+    "export": false,
+  }, {
+    "global": "shown",
+    "export": "visible",
+  }, {
+    "set": "hidden",
+    "value": 2,
+  }, {
+    "set": "shown",
+    "value": 3,
+  }, {
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "call": "compute",
+        "args": [30],
+      },
+      "argr": {
+        "binop": "*",
+        "argl": "hidden",
+        "argr": "shown",
+      },
+    },
+  }]);
+  assertEquals(c, 2 + 6);
+  const exports = fs.readFileSync("test.wat").toString().split("\n").filter(line => line.includes("(export"))
+    .map(line => line.match(/"(.*)"/)[1]).sort();
+  assertEquals(JSON.stringify(exports), JSON.stringify(["#main", "out", "public_compute", "visible"]));
+});
+
+test("export.private_import", () => {
+  fs.writeFileSync("./test_private.jsonlang", JSON.stringify([{
+    "function": "secret",
+    "args": [],
+    "export": false,
+    "block": [{
+      "return": 1,
+    }],
+  }, {
+    "function": "open",
+    "args": [],
+    "block": [{
+      "return": {
+        "binop": "+",
+        "argl": {
+          "call": "secret",
+          "args": [],
+        },
+        "argr": 1,
+      },
+    }],
+  }]));
+  const c = run([{
+    "import": "test_private.jsonlang",
+    "as": "p",
+  }, {
+    "set": "out",
+    "value": {
+      "call": "p.open",
+      "args": [],
+    },
+  }]);
+  assertEquals(c, 2);
+  expectError([{
+    "import": "test_private.jsonlang",
+    "as": "p",
+  }, {
+    "set": "out",
+    "value": {
+      "call": "p.secret",
+      "args": [],
+    },
+  }], "secret is private to p");
+  cp.execSync("rm test_private.jsonlang");
+});
+
+test("entry.rename", () => {
+  const exports = instantiate([{
+    "set": "out",
+    "value": 5,
+  }], "--entry=run");
+  if (exports["#main"]) throw new Error("#main exported");
+  exports.run();
+  assertEquals(exports.out.value, 5);
+});
+
+test("entry.start", () => {
+  const exports = instantiate([{
+    "set": "out",
+    "value": 6,
+  }], "--start --no-entry");
+  assertEquals(exports.out.value, 6);
+  assertEquals(JSON.stringify(Object.keys(exports)), JSON.stringify(["out"]));
+});
+
+test("entry.no_top_level", () => {
+  const exports = instantiate([{
+    "function": "twice",
+    "args": ["x"],
+    "block": [{
+      "return": {
+        "binop": "*",
+        "argl": "x",
+        "argr": 2,
+      },
+    }],
+  }], "--start");
+  if (fs.readFileSync("test.wat").toString().includes("#main")) throw new Error("#main generated");
+  assertEquals(exports.twice(4), 8);
+});
+
+test("global.declaration", () => {
+  const c = run([{
+    "global": "counter",
+    "value": 10,
+  }, {
+    "global": "limit",
+    "value": -2.5,
+    "mutable": false,
+  }, {
+    "declare": "scale",
+    "value": {
+      "binop": "*",
+      "argl": 2,
+      "argr": 3,
+    },
+  }, {
+    "function": "step",
+    "args": [],
+    "block": [{
+      "set": "counter",
+      "value": {
+        "binop": "+",
+        "argl": "counter",
+        "argr": "scale",
+      },
+    }, {
+      "return": "counter",
+    }],
+  }, {
+    "call": "step",
+    "args": [],
+  }, {
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "call": "step",
+        "args": [],
+      },
+      "argr": "limit",
+    },
+  }]);
+  assertEquals(c, 10 + 6 + 6 - 2.5);
+  const wat = fs.readFileSync("test.wat").toString();
+  if (!wat.includes("(global f64 (f64.const -2.5))") || !wat.includes("(global (mut f64) (f64.const 10))")) {
+    throw new Error("Globals not initialized in their declaration");
+  }
+});
+
+test("global.immutable", () => {
+  expectError([{
+    "global": "limit",
+    "value": 1,
+    "mutable": false,
+  }, {
+    "set": "limit",
+    "value": 2,
+  }, {
+    "set": "out",
+    "value": "limit",
+  }], "Global limit is immutable");
+});
+
+test("global.import", () => {
+  fs.writeFileSync("./test_config.jsonlang", JSON.stringify([{
+    "declare": "size",
+    "value": 4,
+  }]));
+  const c = run([{
+    "import": "test_config.jsonlang",
+    "as": "config",
+  }, {
+    "declare": "size",
+    "value": 1,
+  }, {
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "binop": "*",
+        "argl": "config.size",
+        "argr": 10,
+      },
+      "argr": "size",
+    },
+  }]);
+  cp.execSync("rm test_config.jsonlang");
+  assertEquals(c, 41);
+});
+
+test("const", () => {
+  const c = run([{
+    "const": "width",
+    "value": 8,
+  }, {
+    "const": "area",
+    "value": {
+      "binop": "*",
+      "argl": "width",
+      "argr": {
+        "binop": "^",
+        "argl": 2,
+        "argr": 3,
+      },
+    },
+  }, {
+    "global": "start",
+    "value": {
+      "binop": "-",
+      "argl": "area",
+      "argr": 4,
+    },
+  }, {
+    "function": "scaled",
+    "args": ["x"],
+    "block": [{
+      "const": "factor",
+      "value": {
+        "binop": "/",
+        "argl": "width",
+        "argr": 2,
+      },
+    }, {
+      "return": {
+        "binop": "*",
+        "argl": "x",
+        "argr": "factor",
+      },
+    }],
+  }, {
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "call": "scaled",
+        "args": ["area"],
+      },
+      "argr": "start",
+    },
+  }]);
+  assertEquals(c, 64 * 4 + 60);
+  const wat = fs.readFileSync("test.wat").toString();
+  if (wat.split("\n  (global ").length != 3 || wat.includes("(local")) throw new Error("Constants were given storage");
+});
+
+test("const.errors", () => {
+  expectError([{
+    "const": "k",
+    "value": 1,
+  }, {
+    "set": "k",
+    "value": 2,
+  }, {
+    "set": "out",
+    "value": "k",
+  }], "Can't set constant k");
+  expectError([{
+    "set": "x",
+    "value": 1,
+  }, {
+    "const": "k",
+    "value": "x",
+  }, {
+    "set": "out",
+    "value": "k",
+  }], "x isn't a constant");
+});
+
+test("definite_assignment", () => {
+  expectError([{
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": "missing",
+      "argr": 1,
+    },
+  }], "missing isn't defined, read by set out in the top level code");
+  expectError([{
+    "function": "f",
+    "args": ["x"],
+    "block": [{
+      "return": "y",
+    }],
+  }, {
+    "set": "out",
+    "value": 0,
+  }], "y isn't defined, read by a return in function f");
+  expectError([{
+    "set": "out",
+    "value": "total",
+  }, {
+    "set": "total",
+    "value": 3,
+  }], "total may be read before it is set, by set out in the top level code");
+  expectError([{
+    "if": [{
+      "cond": {
+        "binop": ">",
+        "argl": 1,
+        "argr": 0,
+      },
+      "then": {
+        "set": "v",
+        "value": 1,
+      },
+    }],
+  }, {
+    "set": "out",
+    "value": "v",
+  }], "v may be read before it is set");
+  // Set in both branches, by a called function and before the loop ends
+  assertEquals(run([{
+    "function": "init",
+    "args": [],
+    "block": [{
+      "set": "base",
+      "value": 10,
+    }],
+  }, {
+    "function": "later",
+    "args": [],
+    "block": [{
+      "return": "unset",
+    }],
+  }, {
+    "if": [{
+      "cond": {
+        "binop": ">",
+        "argl": 1,
+        "argr": 0,
+      },
+      "then": {
+        "set": "v",
+        "value": 1,
+      },
+    }],
+    "else": {
+      "set": "v",
+      "value": 2,
+    },
+  }, {
+    "call": "init",
+    "args": [],
+  }, {
+    "until": {
+      "binop": ">",
+      "argl": "w",
+      "argr": 3,
+    },
+    "do": {
+      "set": "w",
+      "value": {
+        "binop": "+",
+        "argl": "base",
+        "argr": "v",
+      },
+    },
+  }, {
+    "set": "unset",
+    "value": 0,
+  }, {
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": "w",
+      "argr": {
+        "call": "later",
+        "args": [],
+      },
+    },
+  }]), 11);
+  // A call only sets the globals its function sets on every path
+  const program = flag => [{
+    "function": "maybe",
+    "args": ["flag"],
+    "block": [{
+      "if": [{
+        "cond": "flag",
+        "then": {
+          "set": "g",
+          "value": 1,
+        },
+      }],
+    }, {
+      "return": 0,
+    }],
+  }, {
+    "function": "always",
+    "args": [],
+    "block": [{
+      "call": "maybe",
+      "args": [0],
+    }, {
+      "set": "g",
+      "value": 2,
+    }],
+  }, flag ? {
+    "call": "maybe",
+    "args": [1],
+  } : {
+    "call": "always",
+    "args": [],
+  }, {
+    "set": "out",
+    "value": "g",
+  }];
+  expectError(program(true), "g may be read before it is set, by set out in the top level code");
+  assertEquals(run(program(false)), 2);
+});
+
+test("lints", () => {
+  const program = [{
+    "function": "helper",
+    "args": [],
+    "export": false,
+    "block": [{
+      "return": 1,
+    }],
+  }, {
+    "function": "f",
+    "args": ["x"],
+    "block": [{
+      "declare": "unused",
+      "value": 1,
+    }, {
+      "declare": "y",
+      "value": "x",
+    }, {
+      "declare": "y",
+      "value": 2,
+    }, {
+      "set": "typo",
+      "value": "y",
+    }, {
+      "while": 1,
+      "do": {
+        "set": "x",
+        "value": 2,
+      },
+    }, {
+      "return": "x",
+    }, {
+      "return": {
+        "call": "f",
+        "args": [1],
+      },
+    }, {
+      "set": "x",
+      "value": 3,
+    }],
+  }, {
+    "set": "out",
+    "value": {
+      "call": "f",
+      "args": [3],
+    },
+  }];
+  const warnings = compileWarnings(program);
+  for (const expected of [
+    "warning: function helper is never called [unused-functions]",
+    "warning: local unused is never read in function f [unused-locals]",
+    "warning: y is declared again in the same block in function f [shadowing]",
+    "warning: set typo implicitly creates a global in function f [implicit-globals]",
+    "warning: condition is always the same in function f [constant-conditions]",
+    "warning: unreachable code after return in function f [unreachable-code]"
+  ]) {
+    if (!warnings.includes(expected)) throw new Error(`Missing ${expected} in ${warnings}`);
+  }
+  // Each statement after the return is reported
+  assertEquals(warnings.split("unreachable code after return").length - 1, 2);
+  const allowed = compileWarnings(program, "--allow=unused-locals --allow=implicit-globals");
+  if (allowed.includes("[unused-locals]") || allowed.includes("[implicit-globals]") || !allowed.includes("[shadowing]")) {
+    throw new Error(allowed);
+  }
+  if (compileWarnings([{
+    "set": "out",
+    "value": 1,
+  }]) != "") throw new Error("Warnings for a clean program");
+});
+
+test("arity", () => {
+  expectError([{
+    "function": "add",
+    "args": ["a", "b"],
+    "block": [{
+      "return": {
+        "binop": "+",
+        "argl": "a",
+        "argr": "b",
+      },
+    }],
+  }, {
+    "function": "g",
+    "args": [],
+    "block": [{
+      "call": "add",
+      "args": [1, 2, 3],
+    }, {
+      "return": {
+        "call": "sin",
+        "args": [],
+      },
+    }],
+  }, {
+    "set": "out",
+    "value": {
+      "call": "add",
+      "args": [{
+        "call": "min",
+        "args": [3],
+      }],
+    },
+  }, {
+    "set": "out",
+    "value": {
+      "call": "sqrt",
+      "args": [{
+        "binop": "+",
+        "argl": "out",
+        "argr": 1,
+      }, 2],
+    },
+  }],
+    "add takes 2 arguments but the call add(1, 2, 3) in function g passes 3",
+    "sin takes 1 arguments but the call sin() in function g passes 0",
+    "add takes 2 arguments but the call add(min(3)) in the top level code passes 1",
+    "min takes 2 arguments but the call min(3) in the top level code passes 1",
+    "sqrt takes 1 arguments but the call sqrt((out + 1), 2) in the top level code passes 2");
+});
+
+test("strict", () => {
+  fs.writeFileSync("./test_strict.jsonlang", JSON.stringify([{
+    "strict": true,
+  }, {
+    "global": "count",
+  }, {
+    "function": "bump",
+    "args": ["by"],
+    "block": [{
+      "declare": "next",
+      "value": {
+        "binop": "+",
+        "argl": "count",
+        "argr": "by",
+      },
+    }, {
+      "set": "count",
+      "value": "next",
+    }],
+  }]));
+  const program = [{
+    "strict": true,
+  }, {
+    "import": "test_strict.jsonlang",
+    "as": "counter",
+  }, {
+    "global": "out",
+  }, {
+    "declare": "steps",
+    "value": 3,
+  }, {
+    "call": "counter.bump",
+    "args": ["steps"],
+  }, {
+    "set": "counter.count",
+    "value": {
+      "binop": "*",
+      "argl": "counter.count",
+      "argr": 2,
+    },
+  }, {
+    "set": "out",
+    "value": "counter.count",
+  }];
+  assertEquals(run(program), 6);
+  expectError([{
+    "strict": true,
+  }, {
+    "global": "total",
+  }, {
+    "function": "add",
+    "args": ["x"],
+    "block": [{
+      "set": "totl",
+      "value": {
+        "binop": "+",
+        "argl": "total",
+        "argr": "x",
+      },
+    }],
+  }], "totl isn't declared, globals must be declared in strict files");
+  expectError([{
+    "strict": true,
+  }, {
+    "set": "out",
+    "value": 1,
+  }], "out isn't declared");
+  // Only the file with the directive is strict
+  fs.writeFileSync("./test_strict.jsonlang", JSON.stringify([{
+    "function": "f",
+    "args": [],
+    "block": [{
+      "set": "made",
+      "value": 1,
+    }],
+  }]));
+  assertEquals(run([{
+    "import": "test_strict.jsonlang",
+    "as": "loose",
+  }, {
+    "call": "loose.f",
+    "args": [],
+  }, {
+    "set": "out",
+    "value": 5,
+  }]), 5);
+  cp.execSync("rm test_strict.jsonlang");
+});
+
+test("continue.iterator", () => {
+  // Sum of the odd numbers up to 9, the even ones continue before adding
+  const c = run([{
+    "set": "out",
+    "value": 0,
+  }, {
+    "iterator": "i",
+    "from": 0,
+    "to": 9,
+    "do": [{
+      "if": [{
+        "cond": {
+          "binop": "==",
+          "argl": {
+            "binop": "%",
+            "argl": "i",
+            "argr": 2,
+          },
+          "argr": 0,
+        },
+        "then": "continue",
+      }],
+    }, {
+      "set": "out",
+      "value": {
+        "binop": "+",
+        "argl": "out",
+        "argr": "i",
+      },
+    }],
+  }]);
+  assertEquals(c, 25);
+});
+
+test("continue.until", () => {
+  // The condition is still checked after a continue
+  const c = run([{
+    "set": "n",
+    "value": 0,
+  }, {
+    "set": "out",
+    "value": 0,
+  }, {
+    "do": [{
+      "set": "n",
+      "value": {
+        "binop": "+",
+        "argl": "n",
+        "argr": 1,
+      },
+    }, {
+      "if": [{
+        "cond": {
+          "binop": "<",
+          "argl": "n",
+          "argr": 4,
+        },
+        "then": "continue",
+      }],
+    }, {
+      "set": "out",
+      "value": {
+        "binop": "+",
+        "argl": "out",
+        "argr": "n",
+      },
+    }],
+    "until": {
+      "binop": ">=",
+      "argl": "n",
+      "argr": 6,
+    },
+  }]);
+  assertEquals(c, 4 + 5 + 6);
+});
+
+test("continue.while", () => {
+  const c = run([{
+    "set": "n",
+    "value": 0,
+  }, {
+    "set": "out",
+    "value": 0,
+  }, {
+    "while": {
+      "binop": "<",
+      "argl": "n",
+      "argr": 10,
+    },
+    "do": [{
+      "set": "n",
+      "value": {
+        "binop": "+",
+        "argl": "n",
+        "argr": 1,
+      },
+    }, {
+      "if": [{
+        "cond": {
+          "binop": ">",
+          "argl": "n",
+          "argr": 3,
+        },
+        "then": "continue",
+      }],
+    }, {
+      "set": "out",
+      "value": {
+        "binop": "+",
+        "argl": "out",
+        "argr": "n",
+      },
+    }],
+  }]);
+  assertEquals(c, 6);
+});
+
+test("continue.nested", () => {
+  // Continue and break in the inner iterator only affect it
+  const c = run([{
+    "set": "out",
+    "value": 0,
+  }, {
+    "iterator": "i",
+    "from": 1,
+    "to": 3,
+    "do": [{
+      "iterator": "j",
+      "from": 1,
+      "to": 3,
+      "do": [{
+        "if": [{
+          "cond": {
+            "binop": "==",
+            "argl": "j",
+            "argr": 2,
+          },
+          "then": "continue",
+        }],
+      }, {
+        "if": [{
+          "cond": {
+            "binop": ">",
+            "argl": "j",
+            "argr": "i",
+          },
+          "then": "break",
+        }],
+      }, {
+        "set": "out",
+        "value": {
+          "binop": "+",
+          "argl": "out",
+          "argr": {
+            "binop": "*",
+            "argl": "i",
+            "argr": "j",
+          },
+        },
+      }],
+    }, {
+      "if": [{
+        "cond": {
+          "binop": "==",
+          "argl": "i",
+          "argr": 1,
+        },
+        "then": "continue",
+      }],
+    }, {
+      "set": "out",
+      "value": {
+        "binop": "+",
+        "argl": "out",
+        "argr": 100,
+      },
+    }],
+  }]);
+  assertEquals(c, 1 + 2 + 3 + 9 + 200);
+});
+
+test("iterator.negative_step", () => {
+  const c = run([{
+    "set": "out",
+    "value": 0,
+  }, {
+    "iterator": "i",
+    "from": 10,
+    "to": 1,
+    "step": -3,
+    "do": {
+      "set": "out",
+      "value": {
+        "binop": "+",
+        "argl": {
+          "binop": "*",
+          "argl": "out",
+          "argr": 100,
+        },
+        "argr": "i",
+      },
+    },
+  }]);
+  assertEquals(c, 10070401);
+});
+
+test("iterator.dynamic_step", () => {
+  // The direction comes from the sign of the step when it isn't a literal
+  const sum = step => run([{
+    "set": "s",
+    "value": step,
+  }, {
+    "set": "out",
+    "value": 0,
+  }, {
+    "iterator": "i",
+    "from": {
+      "binop": "*",
+      "argl": "s",
+      "argr": -2,
+    },
+    "to": {
+      "binop": "*",
+      "argl": "s",
+      "argr": 2,
+    },
+    "step": "s",
+    "do": {
+      "set": "out",
+      "value": {
+        "binop": "+",
+        "argl": {
+          "binop": "*",
+          "argl": "out",
+          "argr": 10,
+        },
+        "argr": {
+          "binop": "+",
+          "argl": "i",
+          "argr": 5,
+        },
+      },
+    },
+  }]);
+  assertEquals(sum(1), 34567);
+  assertEquals(sum(-1), 76543);
+});
+
+test("iterator.evaluated_once", () => {
+  // The bound and the step are computed once even if the body changes what they read
+  const c = run([{
+    "set": "calls",
+    "value": 0,
+  }, {
+    "set": "n",
+    "value": 3,
+  }, {
+    "function": "bound",
+    "args": [],
+    "block": [{
+      "set": "calls",
+      "value": {
+        "binop": "+",
+        "argl": "calls",
+        "argr": 1,
+      },
+    }, {
+      "return": "n",
+    }],
+  }, {
+    "set": "count",
+    "value": 0,
+  }, {
+    "iterator": "i",
+    "from": 1,
+    "to": {
+      "call": "bound",
+      "args": [],
+    },
+    "step": {
+      "binop": "/",
+      "argl": "n",
+      "argr": 3,
+    },
+    "do": [{
+      "set": "n",
+      "value": 100,
+    }, {
+      "set": "count",
+      "value": {
+        "binop": "+",
+        "argl": "count",
+        "argr": 1,
+      },
+    }],
+  }, {
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "binop": "*",
+        "argl": "calls",
+        "argr": 10,
+      },
+      "argr": "count",
+    },
+  }]);
+  assertEquals(c, 13);
+});
+
+test("break.outside_loop", () => {
+  expectError([{
+    "set": "out",
+    "value": 1,
+  }, "break"], "break outside a loop");
+  expectError([{
+    "function": "f",
+    "args": ["x"],
+    "block": [{
+      "if": [{
+        "cond": "x",
+        "then": "continue",
+      }],
+    }, {
+      "return": "x",
+    }],
+  }, {
+    "set": "out",
+    "value": {
+      "call": "f",
+      "args": [1],
+    },
+  }], "continue outside a loop");
+  expectError([{
+    "set": "out",
+    "value": 0,
+  }, {
+    "while": {
+      "binop": "<",
+      "argl": "out",
+      "argr": 3,
+    },
+    "do": [{
+      "set": "out",
+      "value": {
+        "binop": "+",
+        "argl": "out",
+        "argr": 1,
+      },
+    }, {
+      "break": "outer",
+    }],
+  }], "break of outer, which isn't a loop around it");
+});
+
+test("break.labeled", () => {
+  // Finds the first pair with i * j == 12 and leaves both loops
+  const c = run([{
+    "set": "out",
+    "value": 0,
+  }, {
+    "iterator": "i",
+    "from": 1,
+    "to": 9,
+    "label": "rows",
+    "do": {
+      "iterator": "j",
+      "from": 1,
+      "to": 9,
+      "do": {
+        "if": [{
+          "cond": {
+            "binop": "==",
+            "argl": {
+              "binop": "*",
+              "argl": "i",
+              "argr": "j",
+            },
+            "argr": 12,
+          },
+          "then": [{
+            "set": "out",
+            "value": {
+              "binop": "+",
+              "argl": {
+                "binop": "*",
+                "argl": "i",
+                "argr": 10,
+              },
+              "argr": "j",
+            },
+          }, {
+            "break": "rows",
+          }],
+        }],
+      },
+    },
+  }]);
+  assertEquals(c, 26);
+});
+
+test("continue.labeled", () => {
+  // Continuing the outer iterator from the inner one still runs its step, so each row stops at its first multiple of 3
+  const c = run([{
+    "set": "out",
+    "value": 0,
+  }, {
+    "set": "rows",
+    "value": 0,
+  }, {
+    "iterator": "i",
+    "from": 1,
+    "to": 4,
+    "label": "outer",
+    "do": [{
+      "set": "rows",
+      "value": {
+        "binop": "+",
+        "argl": "rows",
+        "argr": 1,
+      },
+    }, {
+      "do": [{
+        "set": "out",
+        "value": {
+          "binop": "+",
+          "argl": "out",
+          "argr": 1,
+        },
+      }, {
+        "if": [{
+          "cond": {
+            "binop": "==",
+            "argl": {
+              "binop": "%",
+              "argl": "out",
+              "argr": 3,
+            },
+            "argr": 0,
+          },
+          "then": {
+            "continue": "outer",
+          },
+        }],
+      }],
+      "until": 0,
+    }],
+  }, {
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "binop": "*",
+        "argl": "rows",
+        "argr": 100,
+      },
+      "argr": "out",
+    },
+  }]);
+  assertEquals(c, 412);
+});
+
+test("switch.table", () => {
+  const program = value => [{
+    "function": "name",
+    "args": ["x"],
+    "block": {
+      "switch": "x",
+      "cases": [{
+        "case": 3,
+        "do": {
+          "return": 30,
+        },
+      }, {
+        "case": 1,
+        "do": {
+          "return": 10,
+        },
+      }, {
+        "case": 2,
+        "do": {
+          "return": 20,
+        },
+      }, {
+        "case": 5,
+        "do": {
+          "return": 50,
+        },
+      }],
+      "default": {
+        "return": -1,
+      },
+    },
+  }, {
+    "set": "out",
+    "value": {
+      "call": "name",
+      "args": [value],
+    },
+  }];
+  assertEquals(run(program(1)), 10);
+  assertEquals(run(program(5)), 50);
+  if (!fs.readFileSync("test.wat").toString().includes("br_table 1 2 0 4 3 4")) throw new Error("Switch without jump table");
+  // Gaps, values out of the range and values that aren't integers go to the default
+  assertEquals(run(program(4)), -1);
+  assertEquals(run(program(9)), -1);
+  assertEquals(run(program(-3)), -1);
+  assertEquals(run(program(2.5)), -1);
+  assertEquals(run(program(0)), -1);
+});
+
+test("switch.chain", () => {
+  const program = value => [{
+    "set": "out",
+    "value": 0,
+  }, {
+    "switch": value,
+    "cases": [{
+      "case": 0.5,
+      "do": {
+        "set": "out",
+        "value": 1,
+      },
+    }, {
+      "case": 1000,
+      "do": {
+        "set": "out",
+        "value": 2,
+      },
+    }],
+  }];
+  assertEquals(run(program(0.5)), 1);
+  assertEquals(run(program(1000)), 2);
+  assertEquals(run(program(3)), 0);
+  if (fs.readFileSync("test.wat").toString().includes("br_table")) throw new Error("Jump table for sparse cases");
+});
+
+test("switch.loops", () => {
+  // Break and continue in a case are for the loop around the switch
+  const c = run([{
+    "set": "out",
+    "value": 0,
+  }, {
+    "iterator": "i",
+    "from": 0,
+    "to": 10,
+    "do": {
+      "switch": {
+        "binop": "%",
+        "argl": "i",
+        "argr": 4,
+      },
+      "cases": [{
+        "case": 0,
+        "do": {
+          "set": "out",
+          "value": {
+            "binop": "+",
+            "argl": "out",
+            "argr": 1,
+          },
+        },
+      }, {
+        "case": 1,
+        "do": "continue",
+      }, {
+        "case": 2,
+        "do": {
+          "set": "out",
+          "value": {
+            "binop": "+",
+            "argl": "out",
+            "argr": 100,
+          },
+        },
+      }, {
+        "case": 3,
+        "do": {
+          "if": [{
+            "cond": {
+              "binop": ">",
+              "argl": "i",
+              "argr": 5,
+            },
+            "then": "break",
+          }],
+        },
+      }],
+    },
+  }]);
+  assertEquals(c, 202);
+  expectError([{
+    "switch": 1,
+    "cases": [{
+      "case": 1,
+      "do": [],
+    }, {
+      "case": 1,
+      "do": [],
+    }],
+  }], "Duplicate case 1 in switch");
+});
+
+test("switch.constants", () => {
+  // Case labels can be constants, resolved before looking for duplicates and building the table
+  const program = value => [{
+    "const": "RED",
+    "value": 1,
+  }, {
+    "const": "GREEN",
+    "value": {
+      "binop": "+",
+      "argl": "RED",
+      "argr": 1,
+    },
+  }, {
+    "function": "code",
+    "args": ["x"],
+    "block": [{
+      "const": "BLUE",
+      "value": 3,
+    }, {
+      "switch": "x",
+      "cases": [{
+        "case": "RED",
+        "do": {
+          "return": 10,
+        },
+      }, {
+        "case": "GREEN",
+        "do": {
+          "return": 20,
+        },
+      }, {
+        "case": "BLUE",
+        "do": {
+          "return": 30,
+        },
+      }],
+      "default": {
+        "return": -1,
+      },
+    }],
+  }, {
+    "set": "out",
+    "value": {
+      "call": "code",
+      "args": [value],
+    },
+  }];
+  assertEquals(run(program(2)), 20);
+  assertEquals(run(program(3)), 30);
+  assertEquals(run(program(4)), -1);
+  if (!fs.readFileSync("test.wat").toString().includes("br_table")) throw new Error("Switch on constants without jump table");
+  expectError([{
+    "const": "ONE",
+    "value": 1,
+  }, {
+    "switch": 1,
+    "cases": [{
+      "case": 1,
+      "do": [],
+    }, {
+      "case": "ONE",
+      "do": [],
+    }],
+  }], "Duplicate case 1 in switch");
+  expectError([{
+    "set": "v",
+    "value": 1,
+  }, {
+    "switch": 1,
+    "cases": [{
+      "case": "v",
+      "do": [],
+    }],
+  }], "v isn't a constant");
+});
+
+test("conditional.select", () => {
+  const abs = value => [{
+    "set": "out",
+    "value": {
+      "cond": {
+        "binop": "<",
+        "argl": value,
+        "argr": 0,
+      },
+      "then": {
+        "unop": "-",
+        "arg": value,
+      },
+      "else": value,
+    },
+  }];
+  assertEquals(run(abs(-7)), 7);
+  assertEquals(run(abs(3)), 3);
+  const wat = fs.readFileSync("test.wat").toString();
+  if (!wat.includes("select") || wat.includes("if (result f64)")) throw new Error("Side effect free values without select");
+  // Nested conditionals in conditions and values
+  const sign = value => [{
+    "declare": "x",
+    "value": value,
+  }, {
+    "set": "out",
+    "value": {
+      "cond": {
+        "cond": {
+          "binop": "==",
+          "argl": "x",
+          "argr": 0,
+        },
+        "then": 0,
+        "else": 1,
+      },
+      "then": {
+        "cond": {
+          "binop": ">",
+          "argl": "x",
+          "argr": 0,
+        },
+        "then": 1,
+        "else": -1,
+      },
+      "else": 0,
+    },
+  }];
+  assertEquals(run(sign(5)), 1);
+  assertEquals(run(sign(-2)), -1);
+  assertEquals(run(sign(0)), 0);
+});
+
+test("conditional.if", () => {
+  // Only the chosen value is evaluated
+  const program = value => [{
+    "set": "calls",
+    "value": 0,
+  }, {
+    "function": "count",
+    "args": ["x"],
+    "block": [{
+      "set": "calls",
+      "value": {
+        "binop": "+",
+        "argl": "calls",
+        "argr": 1,
+      },
+    }, {
+      "return": "x",
+    }],
+  }, {
+    "set": "out",
+    "value": {
+      "binop": "+",
+      "argl": {
+        "cond": value,
+        "then": {
+          "call": "count",
+          "args": [10],
+        },
+        "else": {
+          "call": "count",
+          "args": [20],
+        },
+      },
+      "argr": {
+        "binop": "*",
+        "argl": "calls",
+        "argr": 100,
+      },
+    },
+  }];
+  assertEquals(run(program(1)), 110);
+  assertEquals(run(program(0)), 120);
+  if (!fs.readFileSync("test.wat").toString().includes("if (result f64)")) throw new Error("Calls evaluated by select");
+  // Conversions to integers can trap, so they aren't evaluated unless chosen
+  const bits = value => [{
+    "declare": "x",
+    "value": value,
+  }, {
+    "set": "out",
+    "value": {
+      "cond": {
+        "binop": ">=",
+        "argl": "x",
+        "argr": 0,
+      },
+      "then": {
+        "binop": "&",
+        "argl": "x",
+        "argr": 6,
+      },
+      "else": "x",
+    },
+  }];
+  assertEquals(run(bits(7)), 6);
+  assertEquals(run(bits(-5)), -5);
+  // The condition runs before the values, even when they have no effects
+  const effect = run([{
+    "set": "g",
+    "value": 1,
+  }, {
+    "function": "f",
+    "args": [],
+    "block": [{
+      "set": "g",
+      "value": 5,
+    }, {
+      "return": 1,
+    }],
+  }, {
+    "set": "out",
+    "value": {
+      "cond": {
+        "call": "f",
+        "args": [],
+      },
+      "then": "g",
+      "else": 0,
+    },
+  }]);
+  assertEquals(effect, 5);
+});
+
+test("update.operators", () => {
+  const update = (op, value) => run([{
+    "set": "out",
+    "value": 12,
+  }, {
+    "update": "out",
+    "op": op,
+    "value": value,
+  }]);
+  assertEquals(update("+=", 3), 15);
+  assertEquals(update("-=", 3), 9);
+  assertEquals(update("*=", 3), 36);
+  assertEquals(update("/=", 3), 4);
+  assertEquals(update("%=", 5), 2);
+  assertEquals(update("&=", 10), 8);
+  assertEquals(update("|=", 3), 15);
+  assertEquals(update("<<=", 2), 48);
+  assertEquals(update(">>=", 2), 3);
+  // Locals, globals set from functions, and increments in loops
+  const c = run([{
+    "set": "out",
+    "value": 100,
+  }, {
+    "function": "down",
+    "args": [],
+    "block": [{
+      "decrement": "out",
+    }, {
+      "return": 0,
+    }],
+  }, {
+    "declare": "total",
+    "value": 0,
+  }, {
+    "iterator": "i",
+    "from": 1,
+    "to": 4,
+    "do": [{
+      "declare": "n",
+      "value": "i",
+    }, {
+      "update": "n",
+      "op": "*=",
+      "value": "i",
+    }, {
+      "update": "total",
+      "op": "+=",
+      "value": "n",
+    }],
+  }, {
+    "call": "down",
+    "args": [],
+  }, {
+    "increment": "total",
+  }, {
+    "update": "out",
+    "op": "+=",
+    "value": "total",
+  }]);
+  assertEquals(c, 99 + 31);
+});
+
+test("update.undefined", () => {
+  // Updates don't create globals
+  expectError([{
+    "function": "f",
+    "args": [],
+    "block": [{
+      "increment": "count",
+    }, {
+      "return": 0,
+    }],
+  }, {
+    "set": "out",
+    "value": {
+      "call": "f",
+      "args": [],
+    },
+  }], "count isn't defined, read by the update of count in function f");
+  expectError([{
+    "update": "out",
+    "op": "+=",
+    "value": 1,
+  }, {
+    "set": "out",
+    "value": 2,
+  }], "out may be read before it is set, by the update of out in the top level code");
+  expectError([{
+    "const": "limit",
+    "value": 3,
+  }, {
+    "increment": "limit",
+  }], "Can't set constant limit");
+});
+
+test("update.unused", () => {
+  // A local that is only updated is never read
+  const warnings = compileWarnings([{
+    "function": "f",
+    "args": ["x"],
+    "block": [{
+      "declare": "steps",
+      "value": 0,
+    }, {
+      "declare": "sum",
+      "value": 0,
+    }, {
+      "increment": "steps",
+    }, {
+      "update": "steps",
+      "op": "+=",
+      "value": "steps",
+    }, {
+      "update": "sum",
+      "op": "+=",
+      "value": "x",
+    }, {
+      "return": "sum",
+    }],
+  }, {
+    "set": "out",
+    "value": {
+      "call": "f",
+      "args": [1],
+    },
+  }]);
+  if (!warnings.includes("local steps is never read in function f")) throw new Error(warnings);
+  if (warnings.includes("local sum")) throw new Error(warnings);
+});
+
 cp.execSync("rm test.jsonlang test.wasm test.wat");
 console.log(`Passed ${totalPass}/${totalTests}`);
 process.exit(totalPass == totalTests ? 0 : 1);
\ No newline at end of file